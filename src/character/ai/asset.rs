@@ -0,0 +1,515 @@
+//! Behavior trees described in `.bt.ron` assets.
+//!
+//! Each node names a registered node component by its short type path, with optional
+//! `params` deserialized through reflection:
+//!
+//! ```ron
+//! (
+//!     node: "SequenceNode",
+//!     params: (repeat: true),
+//!     children: [
+//!         (node: "SleepAction", params: (duration: (secs: 1, nanos: 0))),
+//!     ],
+//! )
+//! ```
+
+use std::fmt;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader, ron},
+    ecs::{lifecycle::HookContext, system::SystemState, world::DeferredWorld},
+    platform::collections::HashSet,
+    prelude::*,
+    reflect::{
+        FromType, ReflectFromReflect, TypeRegistration, TypeRegistry, TypeRegistryArc,
+        serde::TypedReflectDeserializer,
+    },
+};
+use serde::{
+    Deserialize,
+    de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+};
+
+use super::{ActiveNode, BehaviorTreeRoot, Blackboard};
+
+/// A component that can be used as a node in behavior tree assets.
+///
+/// Register it with `#[reflect(Component, BehaviorNode)]`, and also `Default` if it can be
+/// used without `params`.
+pub trait BehaviorNode: Component {
+    const CHILDREN: NodeChildren;
+}
+
+/// Number of children a [`BehaviorNode`] accepts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeChildren {
+    None,
+    One,
+    AtMostOne,
+    AtLeastOne,
+}
+
+impl NodeChildren {
+    fn allows(self, count: usize) -> bool {
+        match self {
+            NodeChildren::None => count == 0,
+            NodeChildren::One => count == 1,
+            NodeChildren::AtMostOne => count <= 1,
+            NodeChildren::AtLeastOne => count >= 1,
+        }
+    }
+}
+
+impl fmt::Display for NodeChildren {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NodeChildren::None => "no children",
+            NodeChildren::One => "exactly one child",
+            NodeChildren::AtMostOne => "at most one child",
+            NodeChildren::AtLeastOne => "at least one child",
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct ReflectBehaviorNode {
+    pub children: NodeChildren,
+}
+
+impl<T: BehaviorNode> FromType<T> for ReflectBehaviorNode {
+    fn from_type() -> Self {
+        ReflectBehaviorNode {
+            children: T::CHILDREN,
+        }
+    }
+}
+
+#[derive(Asset, TypePath)]
+pub struct BehaviorTreeAsset {
+    root: NodeDef,
+}
+
+/// A node of a [`BehaviorTreeAsset`], validated on load.
+struct NodeDef {
+    name: &'static str,
+    component: Box<dyn Reflect>,
+    children: Vec<NodeDef>,
+}
+
+/// Builds a behavior tree for this entity from an asset, and rebuilds it when the asset
+/// is modified. Removing this component despawns the tree.
+#[derive(Component)]
+#[component(on_remove = on_remove_behavior_tree)]
+pub struct BehaviorTree {
+    pub handle: Handle<BehaviorTreeAsset>,
+    /// Initial blackboard of the tree. Rebuilt trees keep the blackboard of the previous tree.
+    pub blackboard: Blackboard,
+    root: Option<Entity>,
+}
+
+impl BehaviorTree {
+    pub fn new(handle: Handle<BehaviorTreeAsset>) -> Self {
+        BehaviorTree {
+            handle,
+            blackboard: Blackboard::default(),
+            root: None,
+        }
+    }
+
+    pub fn with_blackboard(mut self, blackboard: Blackboard) -> Self {
+        self.blackboard = blackboard;
+        self
+    }
+
+    /// Root node of the tree, once it is built.
+    pub fn root(&self) -> Option<Entity> {
+        self.root
+    }
+}
+
+fn on_remove_behavior_tree(mut world: DeferredWorld, context: HookContext) {
+    if let Some(root) = world
+        .get::<BehaviorTree>(context.entity)
+        .and_then(|tree| tree.root)
+    {
+        // The root is already gone if this entity is being despawned
+        world.commands().queue(move |world: &mut World| {
+            if let Ok(root) = world.get_entity_mut(root) {
+                root.despawn();
+            }
+        });
+    }
+}
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_asset::<BehaviorTreeAsset>()
+        .init_asset_loader::<BehaviorTreeLoader>()
+        .add_systems(Update, spawn_behavior_trees);
+}
+
+struct BehaviorTreeLoader {
+    type_registry: TypeRegistryArc,
+}
+
+impl FromWorld for BehaviorTreeLoader {
+    fn from_world(world: &mut World) -> Self {
+        BehaviorTreeLoader {
+            type_registry: world.resource::<AppTypeRegistry>().0.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum BehaviorTreeLoadError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for BehaviorTreeLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BehaviorTreeLoadError::Io(e) => write!(f, "could not read behavior tree: {e}"),
+            BehaviorTreeLoadError::Ron(e) => write!(f, "invalid behavior tree: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for BehaviorTreeLoadError {}
+
+impl AssetLoader for BehaviorTreeLoader {
+    type Asset = BehaviorTreeAsset;
+    type Settings = ();
+    type Error = BehaviorTreeLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(BehaviorTreeLoadError::Io)?;
+        let root =
+            parse_node(&bytes, &self.type_registry.read()).map_err(BehaviorTreeLoadError::Ron)?;
+        Ok(BehaviorTreeAsset { root })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bt.ron"]
+    }
+}
+
+fn parse_node(bytes: &[u8], registry: &TypeRegistry) -> Result<NodeDef, ron::error::SpannedError> {
+    let mut deserializer = ron::de::Deserializer::from_bytes(bytes)?;
+    NodeDefSeed { registry }
+        .deserialize(&mut deserializer)
+        .map_err(|e| deserializer.span_error(e))
+}
+
+struct NodeDefSeed<'a> {
+    registry: &'a TypeRegistry,
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum NodeField {
+    Node,
+    Params,
+    Children,
+}
+
+impl<'de> DeserializeSeed<'de> for NodeDefSeed<'_> {
+    type Value = NodeDef;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<NodeDef, D::Error> {
+        deserializer.deserialize_struct("Node", &["node", "params", "children"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for NodeDefSeed<'_> {
+    type Value = NodeDef;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a behavior tree node")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<NodeDef, A::Error> {
+        let mut registration: Option<&TypeRegistration> = None;
+        let mut params = None;
+        let mut children = vec![];
+
+        while let Some(field) = map.next_key()? {
+            match field {
+                NodeField::Node => {
+                    let name = map.next_value::<String>()?;
+                    registration =
+                        Some(node_registration(self.registry, &name).map_err(de::Error::custom)?);
+                }
+                NodeField::Params => {
+                    let registration = registration
+                        .ok_or_else(|| de::Error::custom("`node` must be given before `params`"))?;
+                    params = Some(map.next_value_seed(TypedReflectDeserializer::new(
+                        registration,
+                        self.registry,
+                    ))?);
+                }
+                NodeField::Children => {
+                    children = map.next_value_seed(ChildrenSeed {
+                        registry: self.registry,
+                    })?;
+                }
+            }
+        }
+
+        let registration = registration.ok_or_else(|| de::Error::missing_field("node"))?;
+        let name = registration.type_info().type_path_table().short_path();
+
+        let expected = registration
+            .data::<ReflectBehaviorNode>()
+            .expect("checked in node_registration")
+            .children;
+        if !expected.allows(children.len()) {
+            return Err(de::Error::custom(format!(
+                "`{name}` expects {expected}, found {}",
+                children.len()
+            )));
+        }
+
+        let component = if let Some(params) = params {
+            registration
+                .data::<ReflectFromReflect>()
+                .and_then(|from_reflect| from_reflect.from_reflect(&*params))
+                .ok_or_else(|| de::Error::custom(format!("invalid params for `{name}`")))?
+        } else {
+            registration
+                .data::<ReflectDefault>()
+                .map(|default| default.default())
+                .ok_or_else(|| de::Error::custom(format!("`{name}` requires params")))?
+        };
+
+        Ok(NodeDef {
+            name,
+            component,
+            children,
+        })
+    }
+}
+
+fn node_registration<'a>(
+    registry: &'a TypeRegistry,
+    name: &str,
+) -> Result<&'a TypeRegistration, String> {
+    let registration = registry
+        .get_with_short_type_path(name)
+        .or_else(|| registry.get_with_type_path(name))
+        .ok_or_else(|| format!("unknown behavior tree node `{name}`"))?;
+    if registration.data::<ReflectBehaviorNode>().is_none()
+        || registration.data::<ReflectComponent>().is_none()
+    {
+        return Err(format!("`{name}` is not a behavior tree node"));
+    }
+    Ok(registration)
+}
+
+struct ChildrenSeed<'a> {
+    registry: &'a TypeRegistry,
+}
+
+impl<'de> DeserializeSeed<'de> for ChildrenSeed<'_> {
+    type Value = Vec<NodeDef>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Vec<NodeDef>, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ChildrenSeed<'_> {
+    type Value = Vec<NodeDef>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of behavior tree nodes")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<NodeDef>, A::Error> {
+        let mut children = vec![];
+        while let Some(child) = seq.next_element_seed(NodeDefSeed {
+            registry: self.registry,
+        })? {
+            children.push(child);
+        }
+        Ok(children)
+    }
+}
+
+/// Spawns `node` and its children under `parent`, returning the spawned entity.
+fn spawn_node(
+    world: &mut World,
+    registry: &TypeRegistry,
+    node: &NodeDef,
+    parent: Entity,
+) -> Result<Entity> {
+    let reflect_component = registry
+        .get_type_data::<ReflectComponent>((*node.component).reflect_type_info().type_id())
+        .ok_or_else(|| format!("`{}` is no longer registered as a component", node.name))?;
+
+    let mut entity = world.spawn((Name::new(node.name), ChildOf(parent)));
+    reflect_component.insert(
+        &mut entity,
+        (*node.component).as_partial_reflect(),
+        registry,
+    );
+    let id = entity.id();
+
+    for child in &node.children {
+        spawn_node(world, registry, child, id)?;
+    }
+    Ok(id)
+}
+
+/// Builds trees whose asset just loaded, and rebuilds trees whose asset was modified.
+fn spawn_behavior_trees(
+    world: &mut World,
+    state: &mut SystemState<(
+        MessageReader<AssetEvent<BehaviorTreeAsset>>,
+        Query<(Entity, &BehaviorTree)>,
+    )>,
+) -> Result<()> {
+    let (mut events, trees) = state.get_mut(world);
+    let modified = events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let pending = trees
+        .iter()
+        .filter(|(_, tree)| tree.root.is_none() || modified.contains(&tree.handle.id()))
+        .map(|(entity, tree)| (entity, tree.handle.id()))
+        .collect::<Vec<_>>();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    world.resource_scope(|world, assets: Mut<Assets<BehaviorTreeAsset>>| {
+        for (entity, id) in pending {
+            let Some(asset) = assets.get(id) else {
+                continue;
+            };
+
+            let tree = world.get::<BehaviorTree>(entity).unwrap();
+            let old_root = tree.root;
+            let blackboard = old_root
+                .and_then(|root| world.get::<Blackboard>(root))
+                .unwrap_or(&tree.blackboard)
+                .clone();
+            if let Some(old_root) = old_root {
+                debug!("Rebuilding behavior tree of {entity}");
+                world.despawn(old_root);
+            }
+
+            let root = spawn_node(world, &registry, &asset.root, entity)?;
+            world
+                .entity_mut(root)
+                .insert((BehaviorTreeRoot::new(entity), blackboard, ActiveNode));
+            world.get_mut::<BehaviorTree>(entity).unwrap().root = Some(root);
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::character::ai::{SequenceNode, TimeLimitNode, decorator::InverterNode};
+
+    #[derive(Component, Reflect, Default)]
+    #[reflect(Component, Default, BehaviorNode)]
+    struct TestAction;
+
+    impl BehaviorNode for TestAction {
+        const CHILDREN: NodeChildren = NodeChildren::None;
+    }
+
+    fn registry() -> TypeRegistry {
+        let mut registry = TypeRegistry::default();
+        registry.register::<SequenceNode>();
+        registry.register::<TimeLimitNode>();
+        registry.register::<InverterNode>();
+        registry.register::<TestAction>();
+        registry
+    }
+
+    #[test]
+    fn behavior_tree_spawns_nodes_with_params() {
+        let registry = registry();
+        let node = parse_node(
+            br#"(
+                node: "SequenceNode",
+                params: (repeat: true),
+                children: [
+                    (
+                        node: "TimeLimitNode",
+                        params: (duration: (secs: 2, nanos: 0)),
+                        children: [(node: "TestAction")],
+                    ),
+                    (node: "InverterNode", children: [(node: "TestAction")]),
+                ],
+            )"#,
+            &registry,
+        )
+        .unwrap();
+
+        let mut world = World::new();
+        let parent = world.spawn_empty().id();
+        let root = spawn_node(&mut world, &registry, &node, parent).unwrap();
+
+        assert!(world.get::<SequenceNode>(root).unwrap().repeat);
+        let children = world.get::<Children>(root).unwrap().to_vec();
+        assert_eq!(children.len(), 2);
+        assert_eq!(
+            world.get::<TimeLimitNode>(children[0]).unwrap().duration,
+            Duration::from_secs(2)
+        );
+        assert!(world.entity(children[1]).contains::<InverterNode>());
+    }
+
+    #[test]
+    fn behavior_tree_rejects_unknown_node() {
+        let err = parse_node(br#"(node: "NoSuchNode")"#, &registry())
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string()
+                .contains("unknown behavior tree node `NoSuchNode`")
+        );
+    }
+
+    #[test]
+    fn behavior_tree_rejects_wrong_child_count() {
+        let err = parse_node(
+            br#"(
+                node: "InverterNode",
+                children: [(node: "TestAction"), (node: "TestAction")],
+            )"#,
+            &registry(),
+        )
+        .err()
+        .unwrap();
+        assert!(
+            err.to_string()
+                .contains("`InverterNode` expects exactly one child, found 2")
+        );
+    }
+}
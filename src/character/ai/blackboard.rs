@@ -0,0 +1,402 @@
+//! Memory shared between the nodes of a behavior tree.
+
+#![allow(dead_code)]
+
+use std::{borrow::Cow, marker::PhantomData};
+
+use bevy::{
+    ecs::system::{SystemParam, SystemState},
+    platform::collections::HashMap,
+    prelude::*,
+};
+
+use super::{
+    AbortMode, AiTarget, BehaviorNode, ControlNodeSystem, NodeChildren, NodeResult,
+    ReflectBehaviorNode,
+    decorator::{DecoratorState, GuardState, update_guard},
+};
+
+/// Entity the tree is currently interested in, e.g. the player being chased.
+pub const TARGET_ENTITY: BlackboardKey<Entity> = BlackboardKey::new("target_entity");
+/// Position where [`TARGET_ENTITY`] was last seen.
+pub const LAST_SEEN_POSITION: BlackboardKey<Vec3> = BlackboardKey::new("last_seen_position");
+/// Position the tree's target returns to when it has nothing to do.
+pub const HOME_POSITION: BlackboardKey<Vec3> = BlackboardKey::new("home_position");
+/// Position of the last noise heard by the tree's target.
+pub const NOISE_POSITION: BlackboardKey<Vec3> = BlackboardKey::new("noise_position");
+
+/// A value stored in a [`Blackboard`].
+#[derive(Clone, Copy, PartialEq, Debug, Reflect)]
+pub enum BlackboardValue {
+    Entity(Entity),
+    Vec3(Vec3),
+    Bool(bool),
+    Float(f32),
+}
+
+/// Types that can be stored in a [`Blackboard`].
+pub trait BlackboardType: Sized {
+    fn into_value(self) -> BlackboardValue;
+    fn from_value(value: &BlackboardValue) -> Option<Self>;
+}
+
+macro_rules! impl_blackboard_type {
+    ($ty:ty, $variant:ident) => {
+        impl BlackboardType for $ty {
+            fn into_value(self) -> BlackboardValue {
+                BlackboardValue::$variant(self)
+            }
+
+            fn from_value(value: &BlackboardValue) -> Option<Self> {
+                match value {
+                    BlackboardValue::$variant(v) => Some(*v),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_blackboard_type!(Entity, Entity);
+impl_blackboard_type!(Vec3, Vec3);
+impl_blackboard_type!(bool, Bool);
+impl_blackboard_type!(f32, Float);
+
+/// Name of a blackboard entry, along with the type of its value.
+pub struct BlackboardKey<T> {
+    name: &'static str,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> BlackboardKey<T> {
+    pub const fn new(name: &'static str) -> Self {
+        BlackboardKey {
+            name,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T> Clone for BlackboardKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for BlackboardKey<T> {}
+
+/// Memory of a behavior tree, stored on its [`BehaviorTreeRoot`](super::BehaviorTreeRoot).
+///
+/// Nodes should write to it through [`AiBlackboard`], so that changes are notified.
+#[derive(Component, Default, Clone, Debug)]
+pub struct Blackboard(HashMap<Cow<'static, str>, BlackboardValue>);
+
+impl Blackboard {
+    /// Returns the blackboard with `key` set to `value`, to provide initial values.
+    pub fn with<T: BlackboardType>(mut self, key: BlackboardKey<T>, value: T) -> Self {
+        self.0.insert(key.name.into(), value.into_value());
+        self
+    }
+
+    pub fn get<T: BlackboardType>(&self, key: BlackboardKey<T>) -> Option<T> {
+        self.0.get(key.name).and_then(T::from_value)
+    }
+
+    pub fn get_value(&self, name: &str) -> Option<&BlackboardValue> {
+        self.0.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BlackboardValue)> {
+        self.0.iter().map(|(k, v)| (&**k, v))
+    }
+}
+
+/// Sent when an entry of a [`Blackboard`] is set to a different value or cleared.
+#[derive(Message, Clone, Debug)]
+pub struct BlackboardChanged {
+    /// Root of the behavior tree owning the blackboard.
+    pub root: Entity,
+    pub key: Cow<'static, str>,
+}
+
+/// Reads and writes the blackboard of the behavior tree that a node is part of.
+#[derive(SystemParam)]
+pub struct AiBlackboard<'w, 's> {
+    tree: AiTarget<'w, 's>,
+    blackboards: Query<'w, 's, &'static mut Blackboard>,
+    changed: MessageWriter<'w, BlackboardChanged>,
+}
+
+impl<'w, 's> AiBlackboard<'w, 's> {
+    /// Returns the blackboard for the behavior tree that `node` is part of.
+    pub fn blackboard(&self, node: Entity) -> Result<&Blackboard> {
+        let root = self.tree.get_root(node)?;
+        Ok(self.blackboards.get(root)?)
+    }
+
+    pub fn get<T: BlackboardType>(&self, node: Entity, key: BlackboardKey<T>) -> Result<Option<T>> {
+        Ok(self.blackboard(node)?.get(key))
+    }
+
+    pub fn set<T: BlackboardType>(
+        &mut self,
+        node: Entity,
+        key: BlackboardKey<T>,
+        value: T,
+    ) -> Result<()> {
+        self.set_value(node, key.name.into(), value.into_value())
+    }
+
+    pub fn set_value(
+        &mut self,
+        node: Entity,
+        name: Cow<'static, str>,
+        value: BlackboardValue,
+    ) -> Result<()> {
+        let root = self.tree.get_root(node)?;
+        let mut blackboard = self.blackboards.get_mut(root)?;
+        // Avoid triggering change detection when the value is unchanged
+        if blackboard.0.get(&name) == Some(&value) {
+            return Ok(());
+        }
+        blackboard.0.insert(name.clone(), value);
+        self.changed.write(BlackboardChanged { root, key: name });
+        Ok(())
+    }
+
+    pub fn clear<T: BlackboardType>(&mut self, node: Entity, key: BlackboardKey<T>) -> Result<()> {
+        self.clear_value(node, key.name.into())
+    }
+
+    pub fn clear_value(&mut self, node: Entity, name: Cow<'static, str>) -> Result<()> {
+        let root = self.tree.get_root(node)?;
+        let mut blackboard = self.blackboards.get_mut(root)?;
+        if !blackboard.contains(&name) {
+            return Ok(());
+        }
+        blackboard.0.remove(&name);
+        self.changed.write(BlackboardChanged { root, key: name });
+        Ok(())
+    }
+}
+
+/// Node that writes a value to the blackboard, then succeeds.
+#[derive(Component, Clone, Debug, Reflect)]
+#[require(ControlNodeSystem::new(update_set_blackboard))]
+#[reflect(Component, BehaviorNode)]
+pub struct SetBlackboardNode {
+    pub key: Cow<'static, str>,
+    pub value: BlackboardValue,
+}
+
+impl BehaviorNode for SetBlackboardNode {
+    const CHILDREN: NodeChildren = NodeChildren::None;
+}
+
+impl SetBlackboardNode {
+    pub fn new<T: BlackboardType>(key: BlackboardKey<T>, value: T) -> Self {
+        SetBlackboardNode {
+            key: key.name.into(),
+            value: value.into_value(),
+        }
+    }
+}
+
+fn update_set_blackboard(
+    In(entity): In<Entity>,
+    node: Query<&SetBlackboardNode>,
+    mut blackboard: AiBlackboard,
+) -> Result<NodeResult> {
+    let node = node.get(entity)?;
+    blackboard.set_value(entity, node.key.clone(), node.value)?;
+    Ok(NodeResult::Complete)
+}
+
+/// Node that removes an entry from the blackboard, then succeeds.
+#[derive(Component, Clone, Debug, Reflect)]
+#[require(ControlNodeSystem::new(update_clear_blackboard))]
+#[reflect(Component, BehaviorNode)]
+pub struct ClearBlackboardNode {
+    pub key: Cow<'static, str>,
+}
+
+impl BehaviorNode for ClearBlackboardNode {
+    const CHILDREN: NodeChildren = NodeChildren::None;
+}
+
+impl ClearBlackboardNode {
+    pub fn new<T: BlackboardType>(key: BlackboardKey<T>) -> Self {
+        ClearBlackboardNode {
+            key: key.name.into(),
+        }
+    }
+}
+
+fn update_clear_blackboard(
+    In(entity): In<Entity>,
+    node: Query<&ClearBlackboardNode>,
+    mut blackboard: AiBlackboard,
+) -> Result<NodeResult> {
+    let node = node.get(entity)?;
+    blackboard.clear_value(entity, node.key.clone())?;
+    Ok(NodeResult::Complete)
+}
+
+/// Node that checks whether the blackboard has an entry for `key`.
+///
+/// Without children, succeeds if it does and fails otherwise. With a child, guards it like a
+/// [`ConditionNode`](super::ConditionNode).
+#[derive(Component, Clone, Debug, Reflect)]
+#[require(
+    DecoratorState,
+    GuardState,
+    ControlNodeSystem::new(update_has_blackboard_key)
+)]
+#[reflect(Component, BehaviorNode)]
+pub struct HasBlackboardKeyNode {
+    pub key: Cow<'static, str>,
+    #[reflect(default)]
+    pub abort: AbortMode,
+}
+
+impl BehaviorNode for HasBlackboardKeyNode {
+    const CHILDREN: NodeChildren = NodeChildren::AtMostOne;
+}
+
+impl HasBlackboardKeyNode {
+    pub fn new<T: BlackboardType>(key: BlackboardKey<T>) -> Self {
+        HasBlackboardKeyNode {
+            key: key.name.into(),
+            abort: AbortMode::None,
+        }
+    }
+
+    pub fn with_abort(mut self, abort: AbortMode) -> Self {
+        self.abort = abort;
+        self
+    }
+}
+
+fn update_has_blackboard_key(
+    In(entity): In<Entity>,
+    world: &mut World,
+    ai_target: &mut SystemState<AiTarget<'static, 'static>>,
+) -> Result<NodeResult> {
+    let root = ai_target.get(world).get_root(entity)?;
+    let node = world
+        .get::<HasBlackboardKeyNode>(entity)
+        .ok_or("HasBlackboardKeyNode removed")?;
+    let present = world
+        .get::<Blackboard>(root)
+        .ok_or("Behavior tree root without Blackboard")?
+        .contains(&node.key);
+    update_guard(world, entity, "HasBlackboardKeyNode", present)
+}
+
+/// Whether `node` is a [`HasBlackboardKeyNode`] with [`AbortMode::LowerPriority`].
+pub(super) fn is_lower_priority_guard(world: &World, node: Entity) -> bool {
+    world
+        .get::<HasBlackboardKeyNode>(node)
+        .is_some_and(|node| node.abort == AbortMode::LowerPriority)
+}
+
+/// Returns whether the key of `node` is in the blackboard of `root`, if it is a
+/// [`HasBlackboardKeyNode`] with [`AbortMode::LowerPriority`].
+pub(super) fn lower_priority_condition(world: &World, node: Entity, root: Entity) -> Option<bool> {
+    let node = world
+        .get::<HasBlackboardKeyNode>(node)
+        .filter(|node| node.abort == AbortMode::LowerPriority)?;
+    Some(
+        world
+            .get::<Blackboard>(root)
+            .is_some_and(|blackboard| blackboard.contains(&node.key)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::tests::run_behavior_tree, *};
+    use crate::character::ai::{
+        ActiveNode, BehaviorTreeRoot, NodeOutcome, SequenceNode, SequenceState, update_sequence,
+    };
+
+    #[test]
+    fn blackboard_nodes_write_to_root() {
+        let mut world = World::new();
+        world.init_resource::<Messages<BlackboardChanged>>();
+
+        let target = world.spawn_empty().id();
+        let root = world
+            .spawn((
+                BehaviorTreeRoot::new(target),
+                SequenceNode { repeat: false },
+                SequenceState::default(),
+                ControlNodeSystem::new(update_sequence),
+                ActiveNode,
+            ))
+            .id();
+        world.spawn((
+            SetBlackboardNode::new(HOME_POSITION, Vec3::ONE),
+            ControlNodeSystem::new(update_set_blackboard),
+            ChildOf(root),
+        ));
+        world.spawn((
+            HasBlackboardKeyNode::new(HOME_POSITION),
+            ControlNodeSystem::new(update_has_blackboard_key),
+            ChildOf(root),
+        ));
+
+        run_behavior_tree(&mut world);
+        assert_eq!(
+            world.get::<Blackboard>(root).unwrap().get(HOME_POSITION),
+            Some(Vec3::ONE)
+        );
+        assert_eq!(world.get::<NodeOutcome>(root), Some(&NodeOutcome::Success));
+        assert_eq!(world.resource::<Messages<BlackboardChanged>>().len(), 1);
+    }
+
+    #[test]
+    fn has_blackboard_key_node_fails_when_key_is_cleared() {
+        let mut world = World::new();
+        world.init_resource::<Messages<BlackboardChanged>>();
+
+        let target = world.spawn_empty().id();
+        let root = world
+            .spawn((
+                BehaviorTreeRoot::new(target),
+                Blackboard::default().with(TARGET_ENTITY, target),
+                SequenceNode { repeat: false },
+                SequenceState::default(),
+                ControlNodeSystem::new(update_sequence),
+                ActiveNode,
+            ))
+            .id();
+        world.spawn((
+            ClearBlackboardNode::new(TARGET_ENTITY),
+            ControlNodeSystem::new(update_clear_blackboard),
+            ChildOf(root),
+        ));
+        world.spawn((
+            HasBlackboardKeyNode::new(TARGET_ENTITY),
+            ControlNodeSystem::new(update_has_blackboard_key),
+            ChildOf(root),
+        ));
+
+        run_behavior_tree(&mut world);
+        assert!(
+            !world
+                .get::<Blackboard>(root)
+                .unwrap()
+                .contains("target_entity")
+        );
+        assert_eq!(world.get::<NodeOutcome>(root), Some(&NodeOutcome::Failure));
+    }
+}
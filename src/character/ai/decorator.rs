@@ -0,0 +1,616 @@
+//! Decorator nodes, which wrap a single child and change how it runs or what it reports.
+
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+use bevy::{ecs::system::SystemState, prelude::*};
+
+use super::{
+    AiTarget, BehaviorNode, ControlNodeSystem, NodeChildren, NodeOutcome, NodeResult, NodeSystem,
+    ReflectBehaviorNode, is_interrupted,
+};
+
+/// State shared by decorator nodes.
+#[derive(Component, Default)]
+pub(super) struct DecoratorState {
+    /// Whether the child has been queued since the decorator was activated.
+    started: bool,
+    /// Number of times the child has finished since the decorator was activated.
+    count: u32,
+}
+
+fn decorated_child(world: &World, entity: Entity, name: &str) -> Result<Entity> {
+    let children = world.get::<Children>(entity).map_or(&[][..], |c| &**c);
+    let &child = children
+        .first()
+        .ok_or_else(|| format!("{name} requires exactly one child"))?;
+    if children.len() > 1 {
+        warn!("{name} has more than one child, only the first will be used");
+    }
+    Ok(child)
+}
+
+/// Runs the child of a decorator node.
+///
+/// `on_finish` is called with the outcome each time the child finishes, and returns the result of
+/// the decorator, or `None` to run the child again.
+fn update_decorator(
+    world: &mut World,
+    entity: Entity,
+    name: &str,
+    on_finish: impl FnOnce(NodeOutcome, &DecoratorState) -> Option<NodeResult>,
+) -> Result<NodeResult> {
+    let child = decorated_child(world, entity, name)?;
+    let started = world
+        .get::<DecoratorState>(entity)
+        .ok_or("Decorator node without DecoratorState")?
+        .started;
+
+    if !started || is_interrupted(world, child) {
+        let mut state = world.get_mut::<DecoratorState>(entity).unwrap();
+        *state = DecoratorState {
+            started: true,
+            count: 0,
+        };
+        return Ok(NodeResult::QueueNode(child));
+    }
+
+    let Some(&outcome) = world.get::<NodeOutcome>(child) else {
+        return Ok(NodeResult::Continue);
+    };
+
+    let mut state = world.get_mut::<DecoratorState>(entity).unwrap();
+    state.count += 1;
+    if let Some(result) = on_finish(outcome, &state) {
+        *state = DecoratorState::default();
+        Ok(result)
+    } else {
+        debug!("Running child of {name} again ({} runs)", state.count);
+        Ok(NodeResult::QueueNode(child))
+    }
+}
+
+/// Decorator that turns the success of its child into failure and vice versa.
+#[derive(Component, Clone, Copy, Default, Reflect)]
+#[require(DecoratorState, ControlNodeSystem::new(update_inverter))]
+#[reflect(Component, Default, BehaviorNode)]
+pub struct InverterNode;
+
+impl BehaviorNode for InverterNode {
+    const CHILDREN: NodeChildren = NodeChildren::One;
+}
+
+fn update_inverter(In(entity): In<Entity>, world: &mut World) -> Result<NodeResult> {
+    update_decorator(world, entity, "InverterNode", |outcome, _| {
+        Some(match outcome {
+            NodeOutcome::Success => NodeResult::Failure,
+            NodeOutcome::Failure => NodeResult::Complete,
+        })
+    })
+}
+
+/// Decorator that runs its child `count` times. Fails as soon as the child fails.
+#[derive(Component, Clone, Copy, Reflect)]
+#[require(DecoratorState, ControlNodeSystem::new(update_repeat))]
+#[reflect(Component, BehaviorNode)]
+pub struct RepeatNode {
+    pub count: u32,
+}
+
+impl BehaviorNode for RepeatNode {
+    const CHILDREN: NodeChildren = NodeChildren::One;
+}
+
+fn update_repeat(
+    In(entity): In<Entity>,
+    world: &mut World,
+    node: &mut QueryState<&RepeatNode>,
+) -> Result<NodeResult> {
+    let &RepeatNode { count } = node.get(world, entity)?;
+    update_decorator(
+        world,
+        entity,
+        "RepeatNode",
+        |outcome, state| match outcome {
+            NodeOutcome::Success if state.count < count => None,
+            outcome => Some(outcome.into()),
+        },
+    )
+}
+
+/// Decorator that runs its child until it fails, then succeeds.
+#[derive(Component, Clone, Copy, Default, Reflect)]
+#[require(DecoratorState, ControlNodeSystem::new(update_repeat_until_fail))]
+#[reflect(Component, Default, BehaviorNode)]
+pub struct RepeatUntilFailNode;
+
+impl BehaviorNode for RepeatUntilFailNode {
+    const CHILDREN: NodeChildren = NodeChildren::One;
+}
+
+fn update_repeat_until_fail(In(entity): In<Entity>, world: &mut World) -> Result<NodeResult> {
+    update_decorator(
+        world,
+        entity,
+        "RepeatUntilFailNode",
+        |outcome, _| match outcome {
+            NodeOutcome::Success => None,
+            NodeOutcome::Failure => Some(NodeResult::Complete),
+        },
+    )
+}
+
+/// Decorator that runs its child again up to `retries` times while it fails.
+#[derive(Component, Clone, Copy, Reflect)]
+#[require(DecoratorState, ControlNodeSystem::new(update_retry))]
+#[reflect(Component, BehaviorNode)]
+pub struct RetryNode {
+    pub retries: u32,
+}
+
+impl BehaviorNode for RetryNode {
+    const CHILDREN: NodeChildren = NodeChildren::One;
+}
+
+fn update_retry(
+    In(entity): In<Entity>,
+    world: &mut World,
+    node: &mut QueryState<&RetryNode>,
+) -> Result<NodeResult> {
+    let &RetryNode { retries } = node.get(world, entity)?;
+    update_decorator(world, entity, "RetryNode", |outcome, state| match outcome {
+        NodeOutcome::Failure if state.count <= retries => None,
+        outcome => Some(outcome.into()),
+    })
+}
+
+/// Decorator that finishes with the outcome of its child, then fails immediately
+/// when activated again within `duration`.
+#[derive(Component, Clone, Copy, Reflect)]
+#[require(DecoratorState, CooldownState, ControlNodeSystem::new(update_cooldown))]
+#[reflect(Component, BehaviorNode)]
+pub struct CooldownNode {
+    pub duration: Duration,
+}
+
+impl BehaviorNode for CooldownNode {
+    const CHILDREN: NodeChildren = NodeChildren::One;
+}
+
+impl CooldownNode {
+    pub fn from_seconds(seconds: f32) -> Self {
+        CooldownNode {
+            duration: Duration::from_secs_f32(seconds),
+        }
+    }
+}
+
+#[derive(Component, Default)]
+struct CooldownState {
+    /// Elapsed time at which the cooldown ends.
+    ready_at: Option<Duration>,
+}
+
+fn update_cooldown(
+    In(entity): In<Entity>,
+    world: &mut World,
+    node: &mut QueryState<(&CooldownNode, &CooldownState, &DecoratorState)>,
+) -> Result<NodeResult> {
+    let now = world.resource::<Time>().elapsed();
+    let (&config, cooldown, decorator) = node.get(world, entity)?;
+
+    if !decorator.started && cooldown.ready_at.is_some_and(|ready_at| now < ready_at) {
+        debug!("{entity} is cooling down");
+        return Ok(NodeResult::Failure);
+    }
+
+    let result = update_decorator(world, entity, "CooldownNode", |outcome, _| {
+        Some(outcome.into())
+    })?;
+    if matches!(result, NodeResult::Complete | NodeResult::Failure) {
+        world.get_mut::<CooldownState>(entity).unwrap().ready_at = Some(now + config.duration);
+    }
+    Ok(result)
+}
+
+/// Node that checks a condition against the target of its behavior tree.
+///
+/// Without children, succeeds or fails depending on the condition.
+/// With a child, guards it: the condition is checked each time the node is evaluated,
+/// and the child is stopped with a failure once the condition no longer holds.
+///
+/// See [`AbortMode`] for re-evaluating the condition while another branch is running.
+#[derive(Component)]
+#[require(DecoratorState, GuardState, ControlNodeSystem::new(update_condition))]
+pub struct ConditionNode {
+    condition: Option<NodeSystem<bool>>,
+    abort: AbortMode,
+}
+
+impl ConditionNode {
+    /// Creates a condition from a system that receives the target entity.
+    pub fn new<F, M>(condition: F) -> Self
+    where
+        F: IntoSystem<In<Entity>, bool, M> + 'static,
+    {
+        ConditionNode {
+            condition: Some(NodeSystem::new(condition)),
+            abort: AbortMode::None,
+        }
+    }
+
+    pub fn with_abort(mut self, abort: AbortMode) -> Self {
+        self.abort = abort;
+        self
+    }
+}
+
+/// Whether a guard node, such as [`ConditionNode`], is re-evaluated while it is not running.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Reflect)]
+pub enum AbortMode {
+    /// The condition is only checked when the node is evaluated.
+    #[default]
+    None,
+    /// While a later sibling under a [`SelectorNode`](super::SelectorNode) is running,
+    /// the condition is checked every tick. Once it starts to hold, the running sibling is
+    /// stopped and this node is run instead.
+    LowerPriority,
+}
+
+/// Last result of the condition of a guard node, so that it only aborts other branches when
+/// the condition starts to hold.
+#[derive(Component, Default)]
+pub(super) struct GuardState {
+    passed: bool,
+}
+
+/// Records the result of the condition of guard `node`, and returns `true` if it just started
+/// to hold.
+pub(super) fn guard_started_passing(world: &mut World, node: Entity, passed: bool) -> bool {
+    let Some(mut state) = world.get_mut::<GuardState>(node) else {
+        return passed;
+    };
+    let started = passed && !state.passed;
+    state.passed = passed;
+    started
+}
+
+/// Runs the condition of the [`ConditionNode`] `entity` against `target`.
+fn run_condition(world: &mut World, entity: Entity, target: Entity) -> Result<bool> {
+    // Take the system out to regain access to world
+    let mut condition = world
+        .get_mut::<ConditionNode>(entity)
+        .and_then(|mut c| c.condition.take())
+        .ok_or("ConditionNode without condition system")?;
+    let passed = condition.run(world, target);
+    world
+        .get_mut::<ConditionNode>(entity)
+        .expect("Condition node removed itself")
+        .condition = Some(condition);
+    passed
+}
+
+/// Whether `node` is a [`ConditionNode`] with [`AbortMode::LowerPriority`].
+pub(super) fn is_lower_priority_guard(world: &World, node: Entity) -> bool {
+    world
+        .get::<ConditionNode>(node)
+        .is_some_and(|c| c.abort == AbortMode::LowerPriority)
+}
+
+/// Returns the result of the condition of `node` against `target`, if it is a [`ConditionNode`]
+/// with [`AbortMode::LowerPriority`].
+pub(super) fn lower_priority_condition(
+    world: &mut World,
+    node: Entity,
+    target: Entity,
+) -> Result<Option<bool>> {
+    if !is_lower_priority_guard(world, node) {
+        return Ok(None);
+    }
+    run_condition(world, node, target).map(Some)
+}
+
+fn update_condition(
+    In(entity): In<Entity>,
+    world: &mut World,
+    ai_target: &mut SystemState<AiTarget<'static, 'static>>,
+) -> Result<NodeResult> {
+    let target = ai_target.get(world).get_target(entity)?;
+    let passed = run_condition(world, entity, target)?;
+    update_guard(world, entity, "ConditionNode", passed)
+}
+
+/// Runs a node that checks a condition, given whether it `passed`.
+///
+/// Without children, succeeds or fails depending on the condition. With a child, runs it while
+/// the condition holds.
+pub(super) fn update_guard(
+    world: &mut World,
+    entity: Entity,
+    name: &str,
+    passed: bool,
+) -> Result<NodeResult> {
+    if let Some(mut state) = world.get_mut::<GuardState>(entity) {
+        state.passed = passed;
+    }
+
+    if world.get::<Children>(entity).is_none_or(|c| c.is_empty()) {
+        return Ok(if passed {
+            NodeResult::Complete
+        } else {
+            NodeResult::Failure
+        });
+    }
+
+    if !passed {
+        debug!("Condition of {entity} no longer holds");
+        *world
+            .get_mut::<DecoratorState>(entity)
+            .ok_or("Guard node without DecoratorState")? = DecoratorState::default();
+        return Ok(NodeResult::Failure);
+    }
+
+    update_decorator(world, entity, name, |outcome, _| Some(outcome.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::tests::run_behavior_tree, *};
+    use crate::character::ai::{
+        ActiveNode, BehaviorTreeRoot, LeafNodeResult, SelectorNode, SelectorState,
+        on_add_active_node, on_remove_active_node, update_selector,
+    };
+
+    /// Creates a world that resets leaf results on activation, so children can run again.
+    fn new_world() -> World {
+        let mut world = World::new();
+        world.add_observer(on_add_active_node);
+        world.add_observer(on_remove_active_node);
+        world
+    }
+
+    #[test]
+    fn inverter_node_inverts_child_outcome() {
+        let mut world = new_world();
+
+        let node = world
+            .spawn((
+                InverterNode,
+                DecoratorState::default(),
+                ControlNodeSystem::new(update_inverter),
+                ActiveNode,
+            ))
+            .id();
+        let child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        assert!(world.entity(child).contains::<ActiveNode>());
+
+        world
+            .get_mut::<LeafNodeResult>(child)
+            .unwrap()
+            .set_failure();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Success));
+    }
+
+    #[test]
+    fn repeat_node_runs_child_count_times() {
+        let mut world = new_world();
+
+        let node = world
+            .spawn((
+                RepeatNode { count: 2 },
+                DecoratorState::default(),
+                ControlNodeSystem::new(update_repeat),
+                ActiveNode,
+            ))
+            .id();
+        let child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+
+        world
+            .get_mut::<LeafNodeResult>(child)
+            .unwrap()
+            .set_complete();
+        run_behavior_tree(&mut world);
+        assert!(world.entity(node).contains::<ActiveNode>());
+        assert!(world.entity(child).contains::<ActiveNode>());
+
+        world
+            .get_mut::<LeafNodeResult>(child)
+            .unwrap()
+            .set_complete();
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Success));
+    }
+
+    #[test]
+    fn repeat_until_fail_node_succeeds_when_child_fails() {
+        let mut world = new_world();
+
+        let node = world
+            .spawn((
+                RepeatUntilFailNode,
+                DecoratorState::default(),
+                ControlNodeSystem::new(update_repeat_until_fail),
+                ActiveNode,
+            ))
+            .id();
+        let child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+
+        for _ in 0..3 {
+            world
+                .get_mut::<LeafNodeResult>(child)
+                .unwrap()
+                .set_complete();
+            run_behavior_tree(&mut world);
+            assert!(world.entity(node).contains::<ActiveNode>());
+            assert!(world.entity(child).contains::<ActiveNode>());
+        }
+
+        world
+            .get_mut::<LeafNodeResult>(child)
+            .unwrap()
+            .set_failure();
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert!(!world.entity(child).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Success));
+    }
+
+    #[test]
+    fn retry_node_gives_up_after_retries() {
+        let mut world = new_world();
+
+        let node = world
+            .spawn((
+                RetryNode { retries: 1 },
+                DecoratorState::default(),
+                ControlNodeSystem::new(update_retry),
+                ActiveNode,
+            ))
+            .id();
+        let child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+
+        world
+            .get_mut::<LeafNodeResult>(child)
+            .unwrap()
+            .set_failure();
+        run_behavior_tree(&mut world);
+        assert!(world.entity(node).contains::<ActiveNode>());
+        assert!(world.entity(child).contains::<ActiveNode>());
+
+        world
+            .get_mut::<LeafNodeResult>(child)
+            .unwrap()
+            .set_failure();
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Failure));
+    }
+
+    #[test]
+    fn cooldown_node_fails_until_duration_passes() {
+        let mut world = new_world();
+        world.insert_resource(Time::<()>::default());
+
+        let node = world
+            .spawn((
+                CooldownNode::from_seconds(1.0),
+                DecoratorState::default(),
+                CooldownState::default(),
+                ControlNodeSystem::new(update_cooldown),
+                ActiveNode,
+            ))
+            .id();
+        let child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        world
+            .get_mut::<LeafNodeResult>(child)
+            .unwrap()
+            .set_complete();
+        run_behavior_tree(&mut world);
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Success));
+
+        world.entity_mut(node).insert(ActiveNode);
+        world.entity_mut(node).remove::<NodeOutcome>();
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(child).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Failure));
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(1.1));
+        world.entity_mut(node).insert(ActiveNode);
+        world.entity_mut(node).remove::<NodeOutcome>();
+        run_behavior_tree(&mut world);
+        assert!(world.entity(child).contains::<ActiveNode>());
+    }
+
+    #[derive(Component)]
+    struct Alerted;
+
+    #[test]
+    fn condition_node_stops_child_when_condition_fails() {
+        let mut world = new_world();
+
+        let target = world.spawn(Alerted).id();
+        let node = world
+            .spawn((
+                BehaviorTreeRoot::new(target),
+                ConditionNode::new(|In(target): In<Entity>, q: Query<(), With<Alerted>>| {
+                    q.contains(target)
+                }),
+                DecoratorState::default(),
+                ControlNodeSystem::new(update_condition),
+                ActiveNode,
+            ))
+            .id();
+        let child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        assert!(world.entity(child).contains::<ActiveNode>());
+
+        run_behavior_tree(&mut world);
+        assert!(world.entity(child).contains::<ActiveNode>());
+
+        world.entity_mut(target).remove::<Alerted>();
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert!(!world.entity(child).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Failure));
+    }
+
+    #[test]
+    fn condition_node_aborts_lower_priority_branch() {
+        let mut world = new_world();
+
+        let target = world.spawn_empty().id();
+        let selector = world
+            .spawn((
+                BehaviorTreeRoot::new(target),
+                SelectorNode,
+                SelectorState::default(),
+                ControlNodeSystem::new(update_selector),
+                ActiveNode,
+            ))
+            .id();
+        let guard = world
+            .spawn((
+                ConditionNode::new(|In(target): In<Entity>, q: Query<(), With<Alerted>>| {
+                    q.contains(target)
+                })
+                .with_abort(AbortMode::LowerPriority),
+                DecoratorState::default(),
+                ControlNodeSystem::new(update_condition),
+                ChildOf(selector),
+            ))
+            .id();
+        let guarded = world.spawn((LeafNodeResult::Idle, ChildOf(guard))).id();
+        let fallback = world.spawn((LeafNodeResult::Idle, ChildOf(selector))).id();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(guard).contains::<ActiveNode>());
+        assert!(world.entity(fallback).contains::<ActiveNode>());
+
+        run_behavior_tree(&mut world);
+        assert!(world.entity(fallback).contains::<ActiveNode>());
+
+        world.entity_mut(target).insert(Alerted);
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(fallback).contains::<ActiveNode>());
+        assert!(world.entity(guard).contains::<ActiveNode>());
+        assert!(world.entity(guarded).contains::<ActiveNode>());
+    }
+}
@@ -0,0 +1,1102 @@
+use std::time::Duration;
+
+use bevy::{
+    ecs::{
+        entity::EntityHashSet,
+        system::{BoxedSystem, SystemId, SystemParam, SystemState, lifetimeless::Read},
+    },
+    platform::collections::HashMap,
+    prelude::*,
+};
+
+use crate::{dev_util::debug_entity::WorldExt as _, pause::PausableSystems};
+
+mod asset;
+pub mod blackboard;
+mod decorator;
+mod navigation;
+
+pub use asset::{BehaviorNode, BehaviorTree, NodeChildren, ReflectBehaviorNode};
+pub use blackboard::{AiBlackboard, Blackboard, BlackboardChanged, BlackboardKey};
+pub use decorator::AbortMode;
+
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((asset::plugin, navigation::plugin))
+            .add_message::<BlackboardChanged>()
+            .configure_sets(
+                FixedUpdate,
+                (
+                    AiActionSystems::PreUpdateAction,
+                    AiActionSystems::UpdateAction,
+                )
+                    .chain()
+                    .in_set(PausableSystems),
+            )
+            .add_systems(
+                FixedUpdate,
+                (update_behavior_trees, reset_leaf_results)
+                    .chain()
+                    .in_set(AiActionSystems::PreUpdateAction),
+            )
+            .add_observer(on_add_active_node)
+            .add_observer(on_remove_active_node);
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AiActionSystems {
+    PreUpdateAction,
+    UpdateAction,
+}
+
+#[derive(SystemParam)]
+pub struct AiTarget<'w, 's> {
+    parent: Query<'w, 's, Read<ChildOf>>,
+    root: Query<'w, 's, Read<BehaviorTreeRoot>>,
+}
+
+impl<'w, 's> AiTarget<'w, 's> {
+    /// Returns the root of the behavior tree that `entity` is part of.
+    pub fn get_root(&self, mut entity: Entity) -> Result<Entity> {
+        while !self.root.contains(entity) {
+            entity = self
+                .parent
+                .get(entity)
+                .map_err(|_| "No BehaviorTreeRoot found in parents")?
+                .parent();
+        }
+        Ok(entity)
+    }
+
+    /// Returns the target entity for the behavior tree that `entity` is part of.
+    pub fn get_target(&self, entity: Entity) -> Result<Entity> {
+        let root = self.get_root(entity)?;
+        Ok(self.root.get(root)?.target)
+    }
+}
+
+/// Active leaf nodes should set this to indicate their result to their parent.
+#[derive(Component, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LeafNodeResult {
+    #[default]
+    Idle,
+    Continue,
+    Complete,
+    Failure,
+}
+
+impl LeafNodeResult {
+    pub fn reset(&mut self) {
+        *self = LeafNodeResult::Idle;
+    }
+
+    pub fn set_continue(&mut self) {
+        *self = LeafNodeResult::Continue;
+    }
+
+    pub fn set_complete(&mut self) {
+        *self = LeafNodeResult::Complete;
+    }
+
+    pub fn set_failure(&mut self) {
+        *self = LeafNodeResult::Failure;
+    }
+}
+
+/// Outcome of a node, inserted when it finishes and removed when it is activated again.
+/// Control nodes read this to react to their children.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeOutcome {
+    Success,
+    Failure,
+}
+
+impl From<NodeOutcome> for NodeResult {
+    fn from(outcome: NodeOutcome) -> Self {
+        match outcome {
+            NodeOutcome::Success => NodeResult::Complete,
+            NodeOutcome::Failure => NodeResult::Failure,
+        }
+    }
+}
+
+/// Marker component for currently active nodes.
+/// If a leaf node is active, all its parents are also active.
+#[derive(Component)]
+pub struct ActiveNode;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeResult {
+    Continue,
+    Complete,
+    Failure,
+    QueueNode(Entity),
+}
+
+#[derive(Component)]
+#[require(Blackboard)]
+pub struct BehaviorTreeRoot {
+    pub target: Entity,
+}
+
+impl BehaviorTreeRoot {
+    pub fn new(target: Entity) -> Self {
+        BehaviorTreeRoot { target }
+    }
+}
+
+#[derive(Component)]
+pub struct ControlNodeSystem(Option<NodeSystem<Result<NodeResult>>>);
+
+impl ControlNodeSystem {
+    pub fn new<F, M>(system: F) -> Self
+    where
+        F: IntoSystem<In<Entity>, Result<NodeResult>, M> + 'static,
+    {
+        ControlNodeSystem(Some(NodeSystem::new(system)))
+    }
+}
+
+/// A system taking a node entity, registered to the world on its first run.
+// we have to be able to take this out, as `&mut World` is needed to register/run the system
+pub enum NodeSystem<O: 'static> {
+    Uncached(Option<BoxedSystem<In<Entity>, O>>),
+    Cached(SystemId<In<Entity>, O>),
+}
+
+impl<O: 'static> NodeSystem<O> {
+    pub fn new<F, M>(system: F) -> Self
+    where
+        F: IntoSystem<In<Entity>, O, M> + 'static,
+    {
+        NodeSystem::Uncached(Some(Box::new(F::into_system(system))))
+    }
+
+    pub fn run(&mut self, world: &mut World, entity: Entity) -> Result<O> {
+        if let NodeSystem::Uncached(system) = self {
+            *self = NodeSystem::Cached(
+                world.register_boxed_system(system.take().expect("this can never be None")),
+            );
+        }
+        match self {
+            NodeSystem::Cached(system_id) => Ok(world.run_system_with(*system_id, entity)?),
+            NodeSystem::Uncached(_) => {
+                unreachable!("This variant has been eliminated above")
+            }
+        }
+    }
+}
+
+/// Evaluates and updates all behavior trees.
+fn update_behavior_trees(
+    world: &mut World,
+    queries: &mut SystemState<(
+        Query<
+            (
+                Entity,
+                Option<&ChildOf>,
+                Option<&Children>,
+                Has<BehaviorTreeRoot>,
+            ),
+            With<ActiveNode>,
+        >,
+        Query<(), With<ActiveNode>>,
+    )>,
+    selectors: &mut QueryState<
+        (Entity, &SelectorState, &Children),
+        (With<SelectorNode>, With<ActiveNode>),
+    >,
+    ai_target: &mut SystemState<AiTarget<'static, 'static>>,
+) -> Result<()> {
+    abort_lower_priority_branches(world, selectors, ai_target)?;
+
+    let (mut active_nodes, is_active) = queries.get(world);
+
+    let mut node_parents = HashMap::new();
+    let mut leaf_nodes = EntityHashSet::new();
+
+    for (id, child_of, children, is_root) in &mut active_nodes {
+        if !is_root && let Some(&ChildOf(parent)) = child_of {
+            node_parents.insert(id, parent);
+        }
+
+        // Nodes without active children are considered leaf nodes
+        if !children_to_slice(children)
+            .iter()
+            .any(|&id| is_active.contains(id))
+        {
+            leaf_nodes.insert(id);
+        }
+    }
+
+    // for &node in &leaf_nodes {
+    //     debug!("Leaf active node: {}", world.debug_entity(node));
+    // }
+
+    let mut leaf_nodes = leaf_nodes.into_iter().collect::<Vec<_>>();
+
+    while let Some(node) = leaf_nodes.pop() {
+        // A node can be queued by several children and may have finished in the meantime
+        if world.get::<ActiveNode>(node).is_none() {
+            continue;
+        }
+
+        // Take the inner out to regain access to world
+        if let Some(mut system) = world
+            .get_mut::<ControlNodeSystem>(node)
+            .and_then(|mut s| s.0.take())
+        {
+            let action_result = system.run(world, node)??;
+            world
+                .get_mut::<ControlNodeSystem>(node)
+                .expect("Control node system removed itself")
+                .0 = Some(system);
+            match action_result {
+                NodeResult::QueueNode(new_node) => {
+                    ensure_active_node(world, new_node);
+                    leaf_nodes.push(new_node);
+                    node_parents.insert(new_node, node);
+                    debug!("Queued {new_node}");
+                }
+                NodeResult::Complete => {
+                    finish_node(
+                        world,
+                        node,
+                        NodeOutcome::Success,
+                        &node_parents,
+                        &mut leaf_nodes,
+                    );
+                }
+                NodeResult::Failure => {
+                    finish_node(
+                        world,
+                        node,
+                        NodeOutcome::Failure,
+                        &node_parents,
+                        &mut leaf_nodes,
+                    );
+                }
+                NodeResult::Continue => {}
+            }
+        } else {
+            let leaf_result = world.get::<LeafNodeResult>(node).copied();
+            if let Some(result) = leaf_result {
+                if result == LeafNodeResult::Complete {
+                    finish_node(
+                        world,
+                        node,
+                        NodeOutcome::Success,
+                        &node_parents,
+                        &mut leaf_nodes,
+                    );
+                } else if result == LeafNodeResult::Failure {
+                    finish_node(
+                        world,
+                        node,
+                        NodeOutcome::Failure,
+                        &node_parents,
+                        &mut leaf_nodes,
+                    );
+                } else if let Some(&parent) = node_parents.get(&node) {
+                    leaf_nodes.push(parent);
+                } else {
+                    error!(
+                        "Non-control node is on root of behavior tree: {}",
+                        world.debug_entity(node)?
+                    );
+                }
+            } else if let Some(&parent) = node_parents.get(&node) {
+                leaf_nodes.push(parent);
+            } else {
+                error!(
+                    "Active node without control system or leaf result: {}",
+                    world.debug_entity(node)?
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Stops the running branch of active selectors when the condition of a higher-priority guard
+/// with [`AbortMode::LowerPriority`] starts to hold, and activates the guard instead.
+///
+/// Guards are [`ConditionNode`] and [`HasBlackboardKeyNode`](blackboard::HasBlackboardKeyNode).
+fn abort_lower_priority_branches(
+    world: &mut World,
+    selectors: &mut QueryState<
+        (Entity, &SelectorState, &Children),
+        (With<SelectorNode>, With<ActiveNode>),
+    >,
+    ai_target: &mut SystemState<AiTarget<'static, 'static>>,
+) -> Result<()> {
+    let running = selectors
+        .iter(world)
+        .filter_map(|(id, state, children)| {
+            let current = state.current?;
+            Some((
+                id,
+                children.get(..current)?.to_vec(),
+                *children.get(current)?,
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    for (selector, higher_priority, running_child) in running {
+        // An abort further up the tree may have stopped this branch already
+        if world.get::<ActiveNode>(running_child).is_none() {
+            continue;
+        }
+        // Only guards can abort, so selectors without them don't need to be part of a tree
+        if !higher_priority.iter().any(|&node| {
+            decorator::is_lower_priority_guard(world, node)
+                || blackboard::is_lower_priority_guard(world, node)
+        }) {
+            continue;
+        }
+
+        let tree = ai_target.get(world);
+        let (root, target) = (tree.get_root(selector)?, tree.get_target(selector)?);
+        for (index, &guard) in higher_priority.iter().enumerate() {
+            let passed = match blackboard::lower_priority_condition(world, guard, root) {
+                Some(passed) => Some(passed),
+                None => decorator::lower_priority_condition(world, guard, target)?,
+            };
+            if !passed.is_some_and(|passed| decorator::guard_started_passing(world, guard, passed))
+            {
+                continue;
+            }
+            debug!("{guard} aborts lower priority branch {running_child}");
+            deactivate_subtree(world, running_child);
+            ensure_active_node(world, guard);
+            world.get_mut::<SelectorState>(selector).unwrap().current = Some(index);
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Deactivates `node`, records its outcome and queues its parent.
+fn finish_node(
+    world: &mut World,
+    node: Entity,
+    outcome: NodeOutcome,
+    node_parents: &HashMap<Entity, Entity>,
+    leaf_nodes: &mut Vec<Entity>,
+) {
+    deactivate_subtree(world, node);
+    world.entity_mut(node).insert(outcome);
+    if let Some(&parent) = node_parents.get(&node) {
+        debug!("Node {node} finished with {outcome:?}, activating parent {parent}");
+        leaf_nodes.push(parent);
+    } else {
+        debug!("Behavior tree {node} finished with {outcome:?}");
+    }
+}
+
+fn reset_leaf_results(mut query: Query<&mut LeafNodeResult, With<ActiveNode>>) {
+    for mut result in &mut query {
+        result.reset();
+    }
+}
+
+fn ensure_active_node(world: &mut World, entity: Entity) {
+    if world.get::<ActiveNode>(entity).is_none() {
+        world
+            .entity_mut(entity)
+            .insert(ActiveNode)
+            .remove::<NodeOutcome>();
+    }
+}
+
+/// Returns `true` if `node` was deactivated from outside before finishing,
+/// e.g. by a time limit on one of its parents.
+fn is_interrupted(world: &World, node: Entity) -> bool {
+    world.get::<ActiveNode>(node).is_none() && world.get::<NodeOutcome>(node).is_none()
+}
+
+fn deactivate_subtree(world: &mut World, entity: Entity) {
+    if world.get::<ActiveNode>(entity).is_some() {
+        world.entity_mut(entity).remove::<ActiveNode>();
+    }
+    if let Some(children) = world.get::<Children>(entity) {
+        let children = children.to_vec();
+        for child in children {
+            deactivate_subtree(world, child);
+        }
+    }
+}
+
+fn on_add_active_node(
+    on: On<Add, ActiveNode>,
+    has_result_or_control: Query<(), Or<(With<LeafNodeResult>, With<ControlNodeSystem>)>>,
+    mut commands: Commands,
+) {
+    if !has_result_or_control.contains(on.entity) {
+        commands.entity(on.entity).insert(LeafNodeResult::Idle);
+    }
+}
+
+fn on_remove_active_node(
+    on: On<Remove, ActiveNode>,
+    has_result: Query<(), With<LeafNodeResult>>,
+    mut commands: Commands,
+) {
+    if has_result.contains(on.entity) {
+        // `try_remove` because the behavior tree can be being despawned
+        commands.entity(on.entity).try_remove::<LeafNodeResult>();
+    }
+}
+
+/// Control node that runs its children in sequence.
+/// Aborts and fails as soon as one of its children fails.
+#[derive(Component, Clone, Copy, Reflect)]
+#[require(SequenceState, ControlNodeSystem::new(update_sequence))]
+#[reflect(Component, BehaviorNode)]
+pub struct SequenceNode {
+    pub repeat: bool,
+}
+
+impl BehaviorNode for SequenceNode {
+    const CHILDREN: NodeChildren = NodeChildren::AtLeastOne;
+}
+
+#[derive(Component, Default)]
+struct SequenceState {
+    current: Option<usize>,
+}
+
+fn update_sequence(
+    In(entity): In<Entity>,
+    world: &mut World,
+    node: &mut QueryState<(&SequenceNode, &mut SequenceState, &Children)>,
+) -> Result<NodeResult> {
+    let (&config, state, children) = node.get(world, entity)?;
+
+    let mut restart = false;
+    if let Some(current) = state.current {
+        let current_entity = *children.get(current).ok_or("Invalid child index")?;
+
+        match world.get::<NodeOutcome>(current_entity) {
+            Some(NodeOutcome::Success) => debug!("{current} completed"),
+            Some(NodeOutcome::Failure) => {
+                debug!("{current} failed, aborting sequence");
+                let (_, mut state, _) = node.get_mut(world, entity)?;
+                state.current = None;
+                return Ok(NodeResult::Failure);
+            }
+            None if is_interrupted(world, current_entity) => restart = true,
+            None => return Ok(NodeResult::Continue),
+        }
+    }
+
+    let (_, mut state, children) = node.get_mut(world, entity)?;
+    if restart {
+        state.current = None;
+    }
+    let current = if let Some(current) = &mut state.current {
+        *current += 1;
+        *current
+    } else {
+        state.current = Some(0);
+        0
+    };
+
+    debug!("Current = {current}");
+
+    if let Some(&cur_child) = children.get(current) {
+        Ok(NodeResult::QueueNode(cur_child))
+    } else if config.repeat && current != 0 && !children.is_empty() {
+        // Queue child 0 if configured to `repeat`, and `current` ran off indices
+        debug!("Repeat");
+        state.current = Some(0);
+        let child = children[0];
+        Ok(NodeResult::QueueNode(child))
+    } else {
+        state.current = None;
+        Ok(NodeResult::Complete)
+    }
+}
+
+fn children_to_slice(c: Option<&Children>) -> &[Entity] {
+    c.map(|c| &**c).unwrap_or(&[])
+}
+
+/// Control node that runs its children in order until one of them succeeds.
+/// Fails if all of its children fail.
+///
+/// Earlier children take priority: a guard child with [`AbortMode::LowerPriority`] interrupts
+/// a later child as soon as its condition holds.
+#[derive(Component, Clone, Copy, Default, Reflect)]
+#[require(SelectorState, ControlNodeSystem::new(update_selector))]
+#[reflect(Component, Default, BehaviorNode)]
+pub struct SelectorNode;
+
+impl BehaviorNode for SelectorNode {
+    const CHILDREN: NodeChildren = NodeChildren::AtLeastOne;
+}
+
+#[derive(Component, Default)]
+struct SelectorState {
+    current: Option<usize>,
+}
+
+fn update_selector(
+    In(entity): In<Entity>,
+    world: &mut World,
+    node: &mut QueryState<(&mut SelectorState, &Children)>,
+) -> Result<NodeResult> {
+    let (state, children) = node.get(world, entity)?;
+
+    let next = match state.current {
+        Some(current) => {
+            let current_entity = *children.get(current).ok_or("Invalid child index")?;
+            match world.get::<NodeOutcome>(current_entity) {
+                Some(NodeOutcome::Success) => {
+                    let (mut state, _) = node.get_mut(world, entity)?;
+                    state.current = None;
+                    return Ok(NodeResult::Complete);
+                }
+                Some(NodeOutcome::Failure) => {
+                    debug!("{current} failed, trying next child");
+                    current + 1
+                }
+                None if is_interrupted(world, current_entity) => 0,
+                None => return Ok(NodeResult::Continue),
+            }
+        }
+        None => 0,
+    };
+
+    let (mut state, children) = node.get_mut(world, entity)?;
+    if let Some(&child) = children.get(next) {
+        state.current = Some(next);
+        Ok(NodeResult::QueueNode(child))
+    } else {
+        state.current = None;
+        Ok(NodeResult::Failure)
+    }
+}
+
+/// How many children of a [`ParallelNode`] have to succeed or fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Reflect)]
+pub enum ParallelPolicy {
+    #[default]
+    RequireAll,
+    RequireOne,
+}
+
+impl ParallelPolicy {
+    fn is_met(self, count: usize, total: usize) -> bool {
+        match self {
+            ParallelPolicy::RequireAll => count == total,
+            ParallelPolicy::RequireOne => count > 0,
+        }
+    }
+}
+
+/// Control node that runs all its children at the same time.
+///
+/// Finishes as soon as the `failure` or `success` policy is met, deactivating children that
+/// are still running. Failure is checked first. Fails if all children finished without
+/// meeting either policy.
+#[derive(Component, Clone, Copy, Reflect)]
+#[require(ParallelState, ControlNodeSystem::new(update_parallel))]
+#[reflect(Component, Default, BehaviorNode)]
+pub struct ParallelNode {
+    pub success: ParallelPolicy,
+    pub failure: ParallelPolicy,
+}
+
+impl BehaviorNode for ParallelNode {
+    const CHILDREN: NodeChildren = NodeChildren::AtLeastOne;
+}
+
+impl Default for ParallelNode {
+    fn default() -> Self {
+        ParallelNode {
+            success: ParallelPolicy::RequireAll,
+            failure: ParallelPolicy::RequireOne,
+        }
+    }
+}
+
+#[derive(Component, Default)]
+struct ParallelState {
+    running: bool,
+}
+
+fn update_parallel(
+    In(entity): In<Entity>,
+    world: &mut World,
+    node: &mut QueryState<(&ParallelNode, &mut ParallelState, &Children)>,
+) -> Result<NodeResult> {
+    let (&config, state, children) = node.get(world, entity)?;
+    let children = children.to_vec();
+    if children.is_empty() {
+        return Err("ParallelNode requires at least one child".into());
+    }
+
+    let running = state.running && !children.iter().all(|&c| is_interrupted(world, c));
+    if !running {
+        node.get_mut(world, entity)?.1.running = true;
+        // Children start running in the same frame, but control nodes among them are
+        // evaluated from the next update.
+        for &child in &children {
+            ensure_active_node(world, child);
+        }
+        return Ok(NodeResult::Continue);
+    }
+
+    let mut succeeded = 0;
+    let mut failed = 0;
+    for &child in &children {
+        match world.get::<NodeOutcome>(child) {
+            Some(NodeOutcome::Success) => succeeded += 1,
+            Some(NodeOutcome::Failure) => failed += 1,
+            None => {}
+        }
+    }
+
+    let result = if config.failure.is_met(failed, children.len()) {
+        NodeResult::Failure
+    } else if config.success.is_met(succeeded, children.len()) {
+        NodeResult::Complete
+    } else if succeeded + failed == children.len() {
+        NodeResult::Failure
+    } else {
+        return Ok(NodeResult::Continue);
+    };
+
+    node.get_mut(world, entity)?.1.running = false;
+    Ok(result)
+}
+
+/// Control node that runs a child for a specified duration.
+/// Finishes with the child's outcome, or fails if the duration expires first.
+#[derive(Component, Clone, Copy, Reflect)]
+#[require(TimeLimitState, ControlNodeSystem::new(update_time_limit))]
+#[reflect(Component, BehaviorNode)]
+pub struct TimeLimitNode {
+    pub duration: Duration,
+}
+
+impl BehaviorNode for TimeLimitNode {
+    const CHILDREN: NodeChildren = NodeChildren::One;
+}
+
+#[cfg(test)]
+impl TimeLimitNode {
+    pub fn from_seconds(seconds: f32) -> Self {
+        TimeLimitNode {
+            duration: Duration::from_secs_f32(seconds),
+        }
+    }
+}
+
+#[derive(Component, Default)]
+pub struct TimeLimitState {
+    timer: Option<Timer>,
+    child_activated: bool,
+}
+
+impl TimeLimitState {
+    /// Timer of the running child, if any.
+    pub fn timer(&self) -> Option<&Timer> {
+        self.timer.as_ref()
+    }
+}
+
+fn update_time_limit(
+    In(entity): In<Entity>,
+    world: &mut World,
+    node: &mut QueryState<(&TimeLimitNode, &mut TimeLimitState, &Children)>,
+) -> Result<NodeResult> {
+    let delta = world.resource::<Time>().delta();
+    let (_, state, children) = node.get(world, entity)?;
+    let interrupted =
+        state.child_activated && children.first().is_some_and(|&c| is_interrupted(world, c));
+    let (&config, mut state, children) = node.get_mut(world, entity)?;
+
+    let &child = children
+        .first()
+        .ok_or("TimeLimitNode requires exactly one child")?;
+    if children.len() > 1 {
+        warn!("TimeLimitNode has more than one child, only the first will be used");
+    }
+
+    // Initialize timer if not started (first run, after reset or after being interrupted)
+    if state.timer.is_none() || interrupted {
+        state.timer = Some(Timer::new(config.duration, TimerMode::Once));
+        state.child_activated = false;
+    }
+
+    let timer_finished = state.timer.as_mut().unwrap().tick(delta).just_finished();
+    let child_activated = state.child_activated;
+
+    // Check if child finished naturally
+    let child_outcome = if child_activated {
+        world.get::<NodeOutcome>(child).copied()
+    } else {
+        None
+    };
+    if let Some(outcome) = child_outcome {
+        let (_, mut state, _) = node.get_mut(world, entity)?;
+        state.timer = None;
+        state.child_activated = false;
+        return Ok(outcome.into());
+    }
+
+    if timer_finished {
+        debug!("Time limit of {entity} expired");
+        let (_, mut state, _) = node.get_mut(world, entity)?;
+        state.timer = None;
+        state.child_activated = false;
+        return Ok(NodeResult::Failure);
+    }
+
+    if !child_activated {
+        let (_, mut state, _) = node.get_mut(world, entity)?;
+        state.child_activated = true;
+        return Ok(NodeResult::QueueNode(child));
+    }
+
+    Ok(NodeResult::Continue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) fn run_behavior_tree(world: &mut World) {
+        world
+            .run_system_cached::<Result<()>, _, _>(update_behavior_trees)
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn ai_target_gets_target_from_root_chain() {
+        let mut world = World::new();
+
+        let target = world.spawn_empty().id();
+        let root = world.spawn(BehaviorTreeRoot::new(target)).id();
+        let child = world.spawn(ChildOf(root)).id();
+        let grandchild = world.spawn(ChildOf(child)).id();
+
+        let mut state: SystemState<AiTarget> = SystemState::new(&mut world);
+        let ai_target = state.get(&world);
+
+        assert_eq!(ai_target.get_target(root).unwrap(), target);
+        assert_eq!(ai_target.get_target(child).unwrap(), target);
+        assert_eq!(ai_target.get_target(grandchild).unwrap(), target);
+    }
+
+    #[test]
+    fn sequence_node_queues_children_in_order() {
+        let mut world = World::new();
+
+        let node = world
+            .spawn((
+                SequenceNode { repeat: false },
+                SequenceState::default(),
+                ControlNodeSystem::new(update_sequence),
+                ActiveNode,
+            ))
+            .id();
+
+        let first_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+        let second_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        assert!(world.entity(first_child).contains::<ActiveNode>());
+        assert!(!world.entity(second_child).contains::<ActiveNode>());
+
+        world
+            .get_mut::<LeafNodeResult>(first_child)
+            .unwrap()
+            .set_complete();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(first_child).contains::<ActiveNode>());
+        assert!(world.entity(second_child).contains::<ActiveNode>());
+
+        world
+            .get_mut::<LeafNodeResult>(second_child)
+            .unwrap()
+            .set_complete();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert!(!world.entity(first_child).contains::<ActiveNode>());
+        assert!(!world.entity(second_child).contains::<ActiveNode>());
+    }
+
+    #[test]
+    fn sequence_node_aborts_on_child_failure() {
+        let mut world = World::new();
+
+        let node = world
+            .spawn((
+                SequenceNode { repeat: true },
+                SequenceState::default(),
+                ControlNodeSystem::new(update_sequence),
+                ActiveNode,
+            ))
+            .id();
+
+        let first_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+        let second_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        world
+            .get_mut::<LeafNodeResult>(first_child)
+            .unwrap()
+            .set_failure();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert!(!world.entity(first_child).contains::<ActiveNode>());
+        assert!(!world.entity(second_child).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Failure));
+    }
+
+    #[test]
+    fn selector_node_stops_at_first_success() {
+        let mut world = World::new();
+
+        let node = world
+            .spawn((
+                SelectorNode,
+                SelectorState::default(),
+                ControlNodeSystem::new(update_selector),
+                ActiveNode,
+            ))
+            .id();
+
+        let first_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+        let second_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+        let third_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        assert!(world.entity(first_child).contains::<ActiveNode>());
+        assert!(!world.entity(second_child).contains::<ActiveNode>());
+
+        world
+            .get_mut::<LeafNodeResult>(first_child)
+            .unwrap()
+            .set_failure();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(first_child).contains::<ActiveNode>());
+        assert!(world.entity(second_child).contains::<ActiveNode>());
+
+        world
+            .get_mut::<LeafNodeResult>(second_child)
+            .unwrap()
+            .set_complete();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert!(!world.entity(third_child).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Success));
+    }
+
+    #[test]
+    fn selector_node_fails_when_all_children_fail() {
+        let mut world = World::new();
+
+        let node = world
+            .spawn((
+                SelectorNode,
+                SelectorState::default(),
+                ControlNodeSystem::new(update_selector),
+                ActiveNode,
+            ))
+            .id();
+
+        let first_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+        let second_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        world
+            .get_mut::<LeafNodeResult>(first_child)
+            .unwrap()
+            .set_failure();
+
+        run_behavior_tree(&mut world);
+        world
+            .get_mut::<LeafNodeResult>(second_child)
+            .unwrap()
+            .set_failure();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Failure));
+    }
+
+    #[test]
+    fn parallel_node_succeeds_when_all_children_succeed() {
+        let mut world = World::new();
+
+        let node = world
+            .spawn((
+                ParallelNode::default(),
+                ParallelState::default(),
+                ControlNodeSystem::new(update_parallel),
+                ActiveNode,
+            ))
+            .id();
+
+        let first_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+        let second_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        assert!(world.entity(first_child).contains::<ActiveNode>());
+        assert!(world.entity(second_child).contains::<ActiveNode>());
+
+        world
+            .get_mut::<LeafNodeResult>(first_child)
+            .unwrap()
+            .set_complete();
+
+        run_behavior_tree(&mut world);
+        assert!(world.entity(node).contains::<ActiveNode>());
+        assert!(!world.entity(first_child).contains::<ActiveNode>());
+        assert!(world.entity(second_child).contains::<ActiveNode>());
+
+        world
+            .get_mut::<LeafNodeResult>(second_child)
+            .unwrap()
+            .set_complete();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Success));
+    }
+
+    #[test]
+    fn parallel_node_fails_and_stops_children_on_failure() {
+        let mut world = World::new();
+
+        let node = world
+            .spawn((
+                ParallelNode {
+                    success: ParallelPolicy::RequireOne,
+                    failure: ParallelPolicy::RequireOne,
+                },
+                ParallelState::default(),
+                ControlNodeSystem::new(update_parallel),
+                ActiveNode,
+            ))
+            .id();
+
+        let first_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+        let second_child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        world
+            .get_mut::<LeafNodeResult>(first_child)
+            .unwrap()
+            .set_failure();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert!(!world.entity(second_child).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Failure));
+    }
+
+    #[test]
+    fn time_limit_node_completes_when_child_finishes() {
+        let mut world = World::new();
+        world.insert_resource(Time::<()>::default());
+
+        let node = world
+            .spawn((
+                TimeLimitNode::from_seconds(1.0),
+                TimeLimitState::default(),
+                ControlNodeSystem::new(update_time_limit),
+                ActiveNode,
+            ))
+            .id();
+        let child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        assert!(world.entity(child).contains::<ActiveNode>());
+
+        world
+            .get_mut::<LeafNodeResult>(child)
+            .unwrap()
+            .set_complete();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert!(!world.entity(child).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Success));
+    }
+
+    #[test]
+    fn time_limit_node_fails_when_child_fails() {
+        let mut world = World::new();
+        world.insert_resource(Time::<()>::default());
+
+        let node = world
+            .spawn((
+                TimeLimitNode::from_seconds(1.0),
+                TimeLimitState::default(),
+                ControlNodeSystem::new(update_time_limit),
+                ActiveNode,
+            ))
+            .id();
+        let child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        world
+            .get_mut::<LeafNodeResult>(child)
+            .unwrap()
+            .set_failure();
+
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Failure));
+    }
+
+    #[test]
+    fn time_limit_node_fails_when_duration_expires() {
+        let mut world = World::new();
+        world.insert_resource(Time::<()>::default());
+
+        let node = world
+            .spawn((
+                TimeLimitNode::from_seconds(1.0),
+                TimeLimitState::default(),
+                ControlNodeSystem::new(update_time_limit),
+                ActiveNode,
+            ))
+            .id();
+        let child = world.spawn((LeafNodeResult::Idle, ChildOf(node))).id();
+
+        run_behavior_tree(&mut world);
+        assert!(world.entity(child).contains::<ActiveNode>());
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(0.5));
+        run_behavior_tree(&mut world);
+        assert!(world.entity(node).contains::<ActiveNode>());
+        assert!(world.entity(child).contains::<ActiveNode>());
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(0.6));
+        run_behavior_tree(&mut world);
+        assert!(!world.entity(node).contains::<ActiveNode>());
+        assert!(!world.entity(child).contains::<ActiveNode>());
+        assert_eq!(world.get::<NodeOutcome>(node), Some(&NodeOutcome::Failure));
+    }
+}
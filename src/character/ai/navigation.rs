@@ -0,0 +1,420 @@
+//! Behavior tree actions that move the target along paths found over the block grid.
+
+use std::{borrow::Cow, f32::consts::FRAC_PI_4, time::Duration};
+
+use avian3d::prelude::*;
+use bevy::{
+    platform::collections::HashSet,
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, futures::check_ready},
+};
+
+use crate::{
+    character::controller::{CharacterController, MovementEvent, MovementEventKind},
+    terrain::{
+        chunk::{Chunk, ChunkUpdated, ReadBlocks},
+        pathfinding::{
+            BlockSnapshot, NavAgent, PathStep, StepKind, chunk_of, find_path, ground_cell,
+        },
+    },
+};
+
+use super::{
+    ActiveNode, AiActionSystems, AiBlackboard, AiTarget, BehaviorNode, LeafNodeResult,
+    NodeChildren, ReflectBehaviorNode,
+    blackboard::{BlackboardKey, BlackboardType, BlackboardValue, TARGET_ENTITY},
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        (
+            invalidate_paths,
+            (move_to_action_update, follow_path_action_update),
+        )
+            .chain()
+            .in_set(AiActionSystems::UpdateAction),
+    );
+}
+
+/// Goals further away than this are not searched for.
+const MAX_PATH_DISTANCE: f32 = 64.0;
+/// How far down to look for the ground below a character.
+const GROUND_SEARCH_DEPTH: i32 = 4;
+/// How far down to look for the ground below a goal, which may be jumping or falling.
+const GOAL_SEARCH_DEPTH: i32 = 8;
+/// How far the goal cell has to move before the path is searched again.
+const REPLAN_DISTANCE: i32 = 2;
+/// Planar distance at which a waypoint counts as reached.
+const WAYPOINT_RADIUS: f32 = 0.4;
+/// Planar distance to a waypoint at which a jump towards it starts.
+const JUMP_DISTANCE: f32 = 1.5;
+/// How long reaching a waypoint may take before the path is considered blocked.
+const WAYPOINT_TIMEOUT: f32 = 3.0;
+
+/// Path the entity is following. Removed when blocks along it change.
+#[derive(Component, Debug)]
+pub struct NavPath {
+    steps: Vec<PathStep>,
+    next: usize,
+    /// Cell the path leads to.
+    goal: IVec3,
+    /// Chunks the path passes through.
+    chunks: Vec<IVec2>,
+    waypoint_timer: Timer,
+}
+
+impl NavPath {
+    fn new(steps: Vec<PathStep>, goal: IVec3) -> Self {
+        let chunks = steps
+            .iter()
+            .map(|step| chunk_of(step.cell))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        NavPath {
+            steps,
+            next: 0,
+            goal,
+            chunks,
+            waypoint_timer: Timer::from_seconds(WAYPOINT_TIMEOUT, TimerMode::Once),
+        }
+    }
+}
+
+/// Path search running on the async compute pool.
+struct PathSearch {
+    task: Task<Option<Vec<PathStep>>>,
+    goal: IVec3,
+    /// Chunks the search reads.
+    chunks: Vec<IVec2>,
+}
+
+fn start_search(blocks: &ReadBlocks, start: IVec3, goal: IVec3, agent: NavAgent) -> PathSearch {
+    // Allow going around obstacles through neighboring chunks
+    let min = chunk_of(start.min(goal)) - IVec2::ONE;
+    let max = chunk_of(start.max(goal)) + IVec2::ONE;
+
+    let mut snapshot = BlockSnapshot::default();
+    for x in min.x..=max.x {
+        for z in min.y..=max.y {
+            if let Some(chunk) = blocks.get_chunk(IVec2::new(x, z)) {
+                snapshot.insert(chunk);
+            }
+        }
+    }
+    let chunks = snapshot.chunks().collect();
+
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let _span = debug_span!("Find path", ?start, ?goal).entered();
+        find_path(|pos| snapshot.get_block(pos), start, goal, &agent)
+    });
+
+    PathSearch { task, goal, chunks }
+}
+
+/// Movement capabilities of a character controller.
+fn nav_agent(controller: &CharacterController, gravity: &Gravity) -> NavAgent {
+    let gravity = gravity.0.length();
+    let height_for_speed = |speed: f32| {
+        if gravity > 0.0 {
+            speed * speed / (2.0 * gravity)
+        } else {
+            f32::INFINITY
+        }
+    };
+
+    let base = NavAgent::default();
+    NavAgent {
+        walkable_slope: controller
+            .max_slope_angle
+            .is_none_or(|angle| angle >= FRAC_PI_4),
+        // Leave some margin to get over the edge of the block
+        jump_height: (height_for_speed(controller.jump_impulse) - 0.2).max(0.0) as i32,
+        // Don't take fall damage
+        max_drop: controller.impact_damage.map_or(base.max_drop, |curve| {
+            base.max_drop.min(height_for_speed(curve.min_speed) as i32)
+        }),
+        ..base
+    }
+}
+
+enum FollowStatus {
+    Moving,
+    Arrived,
+    Blocked,
+}
+
+/// Moves `entity` towards the next waypoint of `path`.
+fn follow_path(
+    path: &mut NavPath,
+    entity: Entity,
+    transform: &mut Transform,
+    commands: &mut Commands,
+    delta: Duration,
+) -> FollowStatus {
+    let planar_offset = |step: &PathStep| {
+        let offset = step.position() - transform.translation;
+        Vec3::new(offset.x, 0.0, offset.z)
+    };
+
+    while let Some(step) = path.steps.get(path.next) {
+        if planar_offset(step).length() > WAYPOINT_RADIUS {
+            break;
+        }
+        path.next += 1;
+        path.waypoint_timer.reset();
+    }
+    let Some(step) = path.steps.get(path.next) else {
+        return FollowStatus::Arrived;
+    };
+
+    if path.waypoint_timer.tick(delta).is_finished() {
+        return FollowStatus::Blocked;
+    }
+
+    let offset = planar_offset(step);
+    transform.rotation = Quat::from_rotation_arc(-Vec3::Z, offset.normalize());
+    commands.trigger(MovementEvent {
+        entity,
+        kind: MovementEventKind::Move(Vec2::Y),
+    });
+    if step.kind == StepKind::Jump && offset.length() < JUMP_DISTANCE {
+        commands.trigger(MovementEvent {
+            entity,
+            kind: MovementEventKind::Jump,
+        });
+    }
+
+    FollowStatus::Moving
+}
+
+/// Moves towards a blackboard entry, which is either an entity or a position, searching
+/// paths as needed. Fails if the entry is missing, the goal is too far, or there is no path.
+#[derive(Component, Reflect)]
+#[reflect(Component, Default, BehaviorNode)]
+pub struct MoveToAction {
+    /// Name of the blackboard entry to move to.
+    pub goal: Cow<'static, str>,
+    /// Distance to the goal at which the action succeeds, e.g. to stop next to an entity.
+    #[reflect(default)]
+    pub acceptance_radius: f32,
+    #[reflect(ignore)]
+    search: Option<PathSearch>,
+}
+
+impl BehaviorNode for MoveToAction {
+    const CHILDREN: NodeChildren = NodeChildren::None;
+}
+
+impl MoveToAction {
+    pub fn new<T: BlackboardType>(goal: BlackboardKey<T>) -> Self {
+        MoveToAction {
+            goal: goal.name().into(),
+            acceptance_radius: 0.0,
+            search: None,
+        }
+    }
+}
+
+impl Default for MoveToAction {
+    fn default() -> Self {
+        MoveToAction::new(TARGET_ENTITY)
+    }
+}
+
+fn move_to_action_update(
+    ai_target: AiTarget,
+    blackboard: AiBlackboard,
+    mut query: Query<(
+        Entity,
+        &mut MoveToAction,
+        &mut LeafNodeResult,
+        Ref<ActiveNode>,
+    )>,
+    mut transforms: Query<&mut Transform>,
+    mut agents: Query<(&CharacterController, Option<&mut NavPath>)>,
+    blocks: ReadBlocks,
+    gravity: Res<Gravity>,
+    mut commands: Commands,
+    time: Res<Time>,
+) -> Result<()> {
+    let get_block = |pos: IVec3| blocks.get_block(pos).ok().map(|(block, _)| block);
+
+    for (id, mut action, mut result, active) in &mut query {
+        if active.is_added() {
+            action.search = None;
+        }
+
+        let goal_position = match blackboard.blackboard(id)?.get_value(&action.goal) {
+            Some(&BlackboardValue::Entity(entity)) => {
+                transforms.get(entity).ok().map(|t| t.translation)
+            }
+            Some(&BlackboardValue::Vec3(position)) => Some(position),
+            _ => None,
+        };
+        let Some(goal_position) = goal_position else {
+            debug!("No goal `{}` to move to", action.goal);
+            result.set_failure();
+            continue;
+        };
+
+        let target = ai_target.get_target(id)?;
+        let (controller, mut path) = agents.get_mut(target)?;
+        let mut transform = transforms.get_mut(target)?;
+
+        if transform.translation.distance(goal_position) > MAX_PATH_DISTANCE {
+            debug!("Goal `{}` is too far away", action.goal);
+            result.set_failure();
+            continue;
+        }
+        if transform.translation.distance(goal_position) <= action.acceptance_radius {
+            action.search = None;
+            commands.entity(target).remove::<NavPath>();
+            result.set_complete();
+            continue;
+        }
+
+        if let Some(search) = &mut action.search
+            && let Some(steps) = check_ready(&mut search.task)
+        {
+            let goal = search.goal;
+            action.search = None;
+            let Some(steps) = steps else {
+                debug!("No path from {target} to {goal}");
+                commands.entity(target).remove::<NavPath>();
+                result.set_failure();
+                continue;
+            };
+            // Takes effect next update
+            commands.entity(target).insert(NavPath::new(steps, goal));
+            result.set_continue();
+            continue;
+        }
+
+        let goal = ground_cell(get_block, goal_position, GOAL_SEARCH_DEPTH);
+        let needs_search = match (&path, goal) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(path), Some(goal)) => (goal - path.goal).abs().max_element() > REPLAN_DISTANCE,
+        };
+        if needs_search
+            && action.search.is_none()
+            && let Some(goal) = goal
+            && let Some(start) = ground_cell(get_block, transform.translation, GROUND_SEARCH_DEPTH)
+        {
+            action.search = Some(start_search(
+                &blocks,
+                start,
+                goal,
+                nav_agent(controller, &gravity),
+            ));
+        }
+
+        let Some(path) = path.as_deref_mut() else {
+            if goal.is_none() && action.search.is_none() {
+                debug!("Goal `{}` is not on the ground", action.goal);
+                result.set_failure();
+            } else {
+                result.set_continue();
+            }
+            continue;
+        };
+
+        match follow_path(path, target, &mut transform, &mut commands, time.delta()) {
+            FollowStatus::Moving => result.set_continue(),
+            FollowStatus::Arrived => {
+                commands.entity(target).remove::<NavPath>();
+                result.set_complete();
+            }
+            FollowStatus::Blocked => {
+                debug!("Path of {target} is blocked, searching again");
+                commands.entity(target).remove::<NavPath>();
+                result.set_continue();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Follows the [`NavPath`] of the target. Fails if there is none or it is blocked.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component, Default, BehaviorNode)]
+pub struct FollowPathAction;
+
+impl BehaviorNode for FollowPathAction {
+    const CHILDREN: NodeChildren = NodeChildren::None;
+}
+
+fn follow_path_action_update(
+    ai_target: AiTarget,
+    mut query: Query<(Entity, &mut LeafNodeResult), (With<FollowPathAction>, With<ActiveNode>)>,
+    mut agents: Query<(&mut Transform, Option<&mut NavPath>)>,
+    mut commands: Commands,
+    time: Res<Time>,
+) -> Result<()> {
+    for (id, mut result) in &mut query {
+        let target = ai_target.get_target(id)?;
+        let (mut transform, path) = agents.get_mut(target)?;
+        let Some(mut path) = path else {
+            result.set_failure();
+            continue;
+        };
+
+        match follow_path(
+            &mut path,
+            target,
+            &mut transform,
+            &mut commands,
+            time.delta(),
+        ) {
+            FollowStatus::Moving => result.set_continue(),
+            FollowStatus::Arrived => {
+                commands.entity(target).remove::<NavPath>();
+                result.set_complete();
+            }
+            FollowStatus::Blocked => {
+                commands.entity(target).remove::<NavPath>();
+                result.set_failure();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Drops paths and searches that went through chunks whose blocks changed.
+fn invalidate_paths(
+    mut updated: MessageReader<ChunkUpdated>,
+    chunks: Query<&Chunk>,
+    paths: Query<(Entity, &NavPath)>,
+    mut actions: Query<&mut MoveToAction>,
+    mut commands: Commands,
+) {
+    let updated = updated
+        .read()
+        .filter_map(|&ChunkUpdated(id)| chunks.get(id).ok())
+        .map(|chunk| chunk.position)
+        .collect::<HashSet<_>>();
+    if updated.is_empty() {
+        return;
+    }
+
+    for (entity, path) in &paths {
+        if path.chunks.iter().any(|chunk| updated.contains(chunk)) {
+            debug!("Terrain changed along the path of {entity}");
+            commands.entity(entity).remove::<NavPath>();
+        }
+    }
+
+    for mut action in &mut actions {
+        if action
+            .search
+            .as_ref()
+            .is_some_and(|search| search.chunks.iter().any(|chunk| updated.contains(chunk)))
+        {
+            action.search = None;
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! Plays the animation clips of character models according to what the character is doing.
+//!
+//! Clips are looked up by name in the glTF file of the model. Missing clips are skipped, so
+//! models without some or all of the animations still work.
+
+use std::time::Duration;
+
+use avian3d::prelude::*;
+use bevy::{
+    ecs::{lifecycle::HookContext, world::DeferredWorld},
+    gltf::Gltf,
+    platform::collections::HashMap,
+    prelude::*,
+    scene::SceneInstanceReady,
+};
+
+use crate::{
+    character::health::{DamageEvent, DeathEvent},
+    pause::PausableSystems,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<ModelAnimations>()
+        .add_observer(play_hit_animation)
+        .add_observer(play_death_animation)
+        .add_systems(
+            Update,
+            (build_animation_graphs, play_animations)
+                .chain()
+                .in_set(PausableSystems),
+        );
+}
+
+/// Duration of the blend from one animation to the next.
+const TRANSITION_DURATION: Duration = Duration::from_millis(200);
+/// Planar speed above which the walk animation is played.
+const WALK_SPEED: f32 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CharacterAnimation {
+    Idle,
+    Walk,
+    Attack,
+    Hit,
+    Death,
+}
+
+impl CharacterAnimation {
+    const ALL: [CharacterAnimation; 5] = [
+        CharacterAnimation::Idle,
+        CharacterAnimation::Walk,
+        CharacterAnimation::Attack,
+        CharacterAnimation::Hit,
+        CharacterAnimation::Death,
+    ];
+
+    /// Name of the clip in the glTF file.
+    fn clip_name(self) -> &'static str {
+        match self {
+            CharacterAnimation::Idle => "Idle",
+            CharacterAnimation::Walk => "Walk",
+            CharacterAnimation::Attack => "Attack",
+            CharacterAnimation::Hit => "Hit",
+            CharacterAnimation::Death => "Death",
+        }
+    }
+
+    /// Whether the animation loops, as opposed to playing once.
+    fn repeats(self) -> bool {
+        matches!(self, CharacterAnimation::Idle | CharacterAnimation::Walk)
+    }
+}
+
+/// Add this component to the root of a scene to play the animations of the glTF file `model`
+/// on it.
+///
+/// Idle and walk animations follow the velocity of the entity. Other animations are started
+/// with [`Animator::play`], and take over until they finish. Hit and death animations are
+/// played when the entity takes damage and dies.
+#[derive(Component, Clone)]
+#[component(on_add = animator_on_add)]
+pub struct Animator {
+    model: Handle<Gltf>,
+    /// Entity in the scene with the [`AnimationPlayer`].
+    player: Option<Entity>,
+    /// Animation played once over the idle and walk animations.
+    action: Option<CharacterAnimation>,
+    playing: Option<CharacterAnimation>,
+}
+
+impl Animator {
+    pub fn new(model: Handle<Gltf>) -> Self {
+        Animator {
+            model,
+            player: None,
+            action: None,
+            playing: None,
+        }
+    }
+
+    /// Plays `animation` once from the start. Nothing interrupts the death animation.
+    pub fn play(&mut self, animation: CharacterAnimation) {
+        if self.action == Some(CharacterAnimation::Death) {
+            return;
+        }
+        self.action = Some(animation);
+        // Restart it if it is already playing
+        self.playing = None;
+    }
+}
+
+fn animator_on_add(mut world: DeferredWorld, context: HookContext) {
+    world
+        .commands()
+        .entity(context.entity)
+        .observe(find_animation_player);
+}
+
+fn find_animation_player(
+    on: On<SceneInstanceReady>,
+    mut animators: Query<&mut Animator>,
+    children: Query<&Children>,
+    players: Query<(), With<AnimationPlayer>>,
+) {
+    let scene_root = on.event().entity;
+    let Ok(mut animator) = animators.get_mut(scene_root) else {
+        return;
+    };
+
+    animator.player = children
+        .iter_descendants(scene_root)
+        .find(|&id| players.contains(id));
+    if animator.player.is_none() {
+        debug!("Scene of {scene_root} has no AnimationPlayer");
+    }
+}
+
+/// Animation graph of a model, with a clip node for each animation found in it.
+struct AnimationSet {
+    graph: Handle<AnimationGraph>,
+    nodes: HashMap<CharacterAnimation, AnimationNodeIndex>,
+}
+
+/// Animation graphs of loaded models, shared between characters using the same model.
+#[derive(Resource, Default)]
+struct ModelAnimations(HashMap<AssetId<Gltf>, AnimationSet>);
+
+fn build_animation_graphs(
+    animators: Query<&Animator>,
+    gltfs: Res<Assets<Gltf>>,
+    mut graphs: ResMut<Assets<AnimationGraph>>,
+    mut models: ResMut<ModelAnimations>,
+) {
+    for animator in &animators {
+        let id = animator.model.id();
+        if models.0.contains_key(&id) {
+            continue;
+        }
+        let Some(gltf) = gltfs.get(id) else {
+            continue;
+        };
+
+        let mut graph = AnimationGraph::new();
+        let mut nodes = HashMap::new();
+        for animation in CharacterAnimation::ALL {
+            let Some(clip) = gltf.named_animations.get(animation.clip_name()) else {
+                warn!(
+                    "{:?} has no `{}` animation",
+                    animator.model.path(),
+                    animation.clip_name()
+                );
+                continue;
+            };
+            nodes.insert(animation, graph.add_clip(clip.clone(), 1.0, graph.root));
+        }
+
+        models.0.insert(
+            id,
+            AnimationSet {
+                graph: graphs.add(graph),
+                nodes,
+            },
+        );
+    }
+}
+
+fn play_animations(
+    mut animators: Query<(&mut Animator, Option<&LinearVelocity>)>,
+    mut players: Query<(&mut AnimationPlayer, Option<&mut AnimationTransitions>)>,
+    models: Res<ModelAnimations>,
+    mut commands: Commands,
+) {
+    for (mut animator, velocity) in &mut animators {
+        let Some(player_id) = animator.player else {
+            continue;
+        };
+        let Some(animations) = models.0.get(&animator.model.id()) else {
+            continue;
+        };
+        let Ok((mut player, transitions)) = players.get_mut(player_id) else {
+            continue;
+        };
+        let Some(mut transitions) = transitions else {
+            // Takes effect next update
+            commands.entity(player_id).insert((
+                AnimationGraphHandle(animations.graph.clone()),
+                AnimationTransitions::new(),
+            ));
+            continue;
+        };
+
+        if let Some(action) = animator.action
+            && action != CharacterAnimation::Death
+        {
+            let finished = match animations.nodes.get(&action) {
+                // Nothing to wait for
+                None => true,
+                Some(&node) => {
+                    animator.playing == Some(action)
+                        && player.animation(node).is_none_or(|a| a.is_finished())
+                }
+            };
+            if finished {
+                animator.action = None;
+            }
+        }
+
+        let animation = animator.action.unwrap_or_else(|| {
+            let speed = velocity.map_or(0.0, |v| Vec2::new(v.x, v.z).length());
+            if speed > WALK_SPEED {
+                CharacterAnimation::Walk
+            } else {
+                CharacterAnimation::Idle
+            }
+        });
+        if animator.playing == Some(animation) {
+            continue;
+        }
+        let Some(&node) = animations.nodes.get(&animation) else {
+            continue;
+        };
+
+        let active = transitions.play(&mut player, node, TRANSITION_DURATION);
+        if animation.repeats() {
+            active.repeat();
+        }
+        animator.playing = Some(animation);
+    }
+}
+
+fn play_hit_animation(on: On<DamageEvent>, mut animators: Query<&mut Animator>) {
+    if let Ok(mut animator) = animators.get_mut(on.entity()) {
+        animator.play(CharacterAnimation::Hit);
+    }
+}
+
+fn play_death_animation(on: On<DeathEvent>, mut animators: Query<&mut Animator>) {
+    if let Ok(mut animator) = animators.get_mut(on.entity()) {
+        animator.play(CharacterAnimation::Death);
+    }
+}
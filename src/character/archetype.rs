@@ -0,0 +1,208 @@
+//! Kinds of enemies described in `.enemy.ron` assets.
+//!
+//! ```ron
+//! (
+//!     model: "models/Enemy.glb",
+//!     behavior: "behaviors/enemy.bt.ron",
+//!     health: 100.0,
+//!     controller: (movement_acceleration: 50.0),
+//!     loot: (entries: [(item: Some(257), count: (1, 3))]),
+//! )
+//! ```
+//!
+//! Omitted fields take the values of [`EnemyArchetype::default`].
+
+use std::time::Duration;
+
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use bevy_common_assets::ron::RonAssetPlugin;
+use serde::Deserialize;
+
+use crate::{
+    character::{
+        ai::{BehaviorTree, Blackboard, blackboard::HOME_POSITION},
+        animation::Animator,
+        controller::CharacterController,
+        enemy::{DropItemOnDeath, Enemy},
+        health::Health,
+        perception::Perception,
+    },
+    dev_util::{
+        debug_annotation::{debug_annot_ui, target::AnnotTargetAabb},
+        mesh_alpha::OverwriteAlpha,
+    },
+    item::loot::LootTable,
+    pause::PausableSystems,
+    physics::GameLayer,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_plugins(RonAssetPlugin::<EnemyArchetype>::new(&["enemy.ron"]))
+        .add_systems(Update, build_enemies.in_set(PausableSystems));
+}
+
+/// A kind of enemy, loaded from `enemies/<name>.enemy.ron`.
+#[derive(Asset, TypePath, Deserialize, Debug)]
+#[serde(default)]
+pub struct EnemyArchetype {
+    /// Path of the glTF model, which provides the colliders and animations.
+    pub model: String,
+    /// Path of the behavior tree.
+    pub behavior: String,
+    pub health: f32,
+    pub mass: f32,
+    pub friction: f32,
+    /// Scale of gravity, e.g. `0.0` for flying enemies.
+    pub gravity_scale: f32,
+    pub controller: ControllerConfig,
+    pub perception: PerceptionConfig,
+    /// Items dropped on death.
+    pub loot: LootTable,
+}
+
+impl Default for EnemyArchetype {
+    fn default() -> Self {
+        EnemyArchetype {
+            model: "models/Enemy.glb".into(),
+            behavior: "behaviors/enemy.bt.ron".into(),
+            health: 100.0,
+            mass: 2.0,
+            friction: 0.5,
+            gravity_scale: 1.0,
+            controller: ControllerConfig::default(),
+            perception: PerceptionConfig::default(),
+            loot: LootTable::default(),
+        }
+    }
+}
+
+/// Tuning of the [`CharacterController`] of an enemy.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ControllerConfig {
+    pub movement_acceleration: f32,
+    pub movement_damping_factor: f32,
+    pub jump_impulse: f32,
+}
+
+impl Default for ControllerConfig {
+    fn default() -> Self {
+        let controller = CharacterController::default();
+        ControllerConfig {
+            movement_acceleration: controller.movement_acceleration,
+            movement_damping_factor: controller.movement_damping_factor,
+            jump_impulse: controller.jump_impulse,
+        }
+    }
+}
+
+impl ControllerConfig {
+    fn controller(&self) -> CharacterController {
+        CharacterController {
+            movement_acceleration: self.movement_acceleration,
+            movement_damping_factor: self.movement_damping_factor,
+            jump_impulse: self.jump_impulse,
+            ..default()
+        }
+    }
+}
+
+/// Senses of an enemy, see [`Perception`].
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PerceptionConfig {
+    pub sight_range: f32,
+    /// Angle of the field of view, in degrees.
+    pub field_of_view: f32,
+    pub hearing: f32,
+    /// Seconds before forgetting where a player was seen or heard.
+    pub memory: f32,
+}
+
+impl Default for PerceptionConfig {
+    fn default() -> Self {
+        let perception = Perception::default();
+        PerceptionConfig {
+            sight_range: perception.sight_range,
+            field_of_view: perception.field_of_view.to_degrees(),
+            hearing: perception.hearing,
+            memory: perception.memory.as_secs_f32(),
+        }
+    }
+}
+
+impl PerceptionConfig {
+    fn perception(&self) -> Perception {
+        Perception {
+            sight_range: self.sight_range,
+            field_of_view: self.field_of_view.to_radians(),
+            hearing: self.hearing,
+            memory: Duration::from_secs_f32(self.memory.max(0.0)),
+        }
+    }
+}
+
+/// Enemy waiting for its archetype to load.
+#[derive(Component)]
+struct PendingEnemy(Handle<EnemyArchetype>);
+
+/// Spawns an enemy of the archetype `name` at `position`, with its home there.
+///
+/// The enemy is built once `enemies/<name>.enemy.ron` is loaded, and despawned if it fails
+/// to load.
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    name: &str,
+    position: Vec3,
+) -> Entity {
+    commands
+        .spawn((
+            Name::new(format!("Enemy ({name})")),
+            Enemy,
+            PendingEnemy(asset_server.load(format!("enemies/{name}.enemy.ron"))),
+            Transform::from_translation(position),
+        ))
+        .id()
+}
+
+fn build_enemies(
+    pending: Query<(Entity, &PendingEnemy, &Transform)>,
+    archetypes: Res<Assets<EnemyArchetype>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for (entity, PendingEnemy(handle), transform) in &pending {
+        let Some(archetype) = archetypes.get(handle) else {
+            if asset_server.load_state(handle).is_failed() {
+                error!("Could not load enemy archetype {:?}", handle.path());
+                commands.entity(entity).despawn();
+            }
+            continue;
+        };
+        commands.entity(entity).remove::<PendingEnemy>().insert((
+            Health::new(archetype.health),
+            Mass(archetype.mass),
+            Friction::new(archetype.friction),
+            GravityScale(archetype.gravity_scale),
+            RigidBody::Dynamic,
+            archetype.controller.controller(),
+            CollisionLayers::new(
+                [GameLayer::Character],
+                [GameLayer::Terrain, GameLayer::Character],
+            ),
+            AnnotTargetAabb,
+            SceneRoot(
+                asset_server.load(GltfAssetLabel::Scene(0).from_asset(archetype.model.clone())),
+            ),
+            Animator::new(asset_server.load(archetype.model.clone())),
+            OverwriteAlpha(0.8),
+            DropItemOnDeath(archetype.loot.clone()),
+            archetype.perception.perception(),
+            BehaviorTree::new(asset_server.load(archetype.behavior.clone()))
+                .with_blackboard(Blackboard::default().with(HOME_POSITION, transform.translation)),
+        ));
+        commands.spawn(debug_annot_ui(entity));
+    }
+}
@@ -4,13 +4,16 @@ use avian3d::prelude::*;
 use bevy::prelude::*;
 use std::f32::consts::PI;
 
-use crate::pause::PausableSystems;
+use crate::{
+    character::health::{DamageKind, deal_damage},
+    pause::PausableSystems,
+};
 
 pub(super) fn plugin(app: &mut App) {
     app.add_observer(movement)
         .add_systems(
             Update,
-            (update_grounded, apply_movement_damping)
+            (update_grounded, apply_impact_damage, apply_movement_damping)
                 .chain()
                 .in_set(PausableSystems),
         )
@@ -32,12 +35,14 @@ pub enum MovementEventKind {
 
 /// A marker component indicating that an entity is using a character controller.
 #[derive(Component, Clone)]
-#[require(RigidBody, LockedAxes::ROTATION_LOCKED, Grounded)]
+#[require(RigidBody, LockedAxes::ROTATION_LOCKED, Grounded, ImpactTracker)]
 pub struct CharacterController {
     pub movement_acceleration: f32,
     pub movement_damping_factor: f32,
     pub jump_impulse: f32,
     pub max_slope_angle: Option<f32>,
+    /// Damage taken from landing and other impacts. `None` disables impact damage.
+    pub impact_damage: Option<ImpactDamageCurve>,
 }
 
 impl Default for CharacterController {
@@ -47,10 +52,41 @@ impl Default for CharacterController {
             movement_damping_factor: 0.9,
             jump_impulse: 7.0,
             max_slope_angle: Some(PI * 0.45),
+            impact_damage: Some(ImpactDamageCurve::default()),
         }
     }
 }
 
+/// Converts impact speed to damage: `((speed - min_speed) * damage_per_speed) ^ exponent`.
+#[derive(Clone, Copy, Debug)]
+pub struct ImpactDamageCurve {
+    /// Impacts slower than this deal no damage.
+    pub min_speed: f32,
+    pub damage_per_speed: f32,
+    pub exponent: f32,
+}
+
+impl Default for ImpactDamageCurve {
+    fn default() -> Self {
+        Self {
+            // About 7 blocks of free fall
+            min_speed: 12.0,
+            damage_per_speed: 4.0,
+            exponent: 1.2,
+        }
+    }
+}
+
+impl ImpactDamageCurve {
+    pub fn damage(&self, speed: f32) -> f32 {
+        let excess = speed - self.min_speed;
+        if excess <= 0.0 {
+            return 0.0;
+        }
+        (excess * self.damage_per_speed).powf(self.exponent)
+    }
+}
+
 fn update_ground_shape_caster(
     controllers: Query<(Entity, Ref<RigidBodyColliders>), With<CharacterController>>,
     mut commands: Commands,
@@ -107,7 +143,7 @@ fn relative_transform(
 pub struct Grounded(Option<Vec3>);
 
 impl Grounded {
-    fn is_grounded(&self) -> bool {
+    pub fn is_grounded(&self) -> bool {
         self.0.is_some()
     }
 }
@@ -124,14 +160,7 @@ fn update_grounded(
         // that isn't too steep.
         let ground_normals = hits.iter().filter_map(|hit| {
             let ground_normal: Vec3 = rotation * -hit.normal2;
-            if let Some(max_slope_angle) = controller.max_slope_angle {
-                if ground_normal.angle_between(Vec3::Y).abs() <= max_slope_angle {
-                    return Some(ground_normal);
-                }
-            } else {
-                return Some(ground_normal);
-            }
-            None
+            is_walkable(ground_normal, controller.max_slope_angle).then_some(ground_normal)
         });
 
         // Get the steepest ground normal (lowest Y component)
@@ -155,6 +184,140 @@ fn update_grounded(
     }
 }
 
+/// Whether a surface with `normal` is flat enough to stand on.
+fn is_walkable(normal: Vec3, max_slope_angle: Option<f32>) -> bool {
+    max_slope_angle
+        .is_none_or(|max_slope_angle| normal.angle_between(Vec3::Y).abs() <= max_slope_angle)
+}
+
+/// Velocity and ground state of the previous frame, used to measure impacts.
+#[derive(Component, Default)]
+pub struct ImpactTracker {
+    previous_velocity: Vec3,
+    was_grounded: bool,
+}
+
+/// Contact impulse applied to a character in the last physics step.
+#[derive(Clone, Copy, Debug)]
+struct ContactImpulse {
+    /// Contact normal pointing towards the character.
+    normal: Vec3,
+    impulse: f32,
+    /// The other body, if any.
+    source: Option<Entity>,
+}
+
+/// Sums the impulses of contacts that are not walkable ground, which is covered by landing
+/// damage, and returns them with the source of the strongest one.
+fn impact_from_contacts(
+    contacts: &[ContactImpulse],
+    max_slope_angle: Option<f32>,
+) -> (f32, Option<Entity>) {
+    let impacts = contacts
+        .iter()
+        .filter(|contact| !is_walkable(contact.normal, max_slope_angle));
+    let total = impacts.clone().map(|contact| contact.impulse).sum();
+    let source = impacts
+        .max_by(|a, b| a.impulse.total_cmp(&b.impulse))
+        .and_then(|contact| contact.source);
+    (total, source)
+}
+
+/// Deals damage to character controllers that land or get hit at high speed.
+///
+/// Landing damage uses the velocity towards the ground before touching it.
+/// Other impacts use the change in velocity caused by contact impulses, with the body of the
+/// strongest contact as the source.
+fn apply_impact_damage(
+    mut query: Query<(
+        Entity,
+        &CharacterController,
+        &Grounded,
+        &LinearVelocity,
+        &ComputedMass,
+        &RigidBodyColliders,
+        &mut ImpactTracker,
+    )>,
+    collisions: Collisions,
+    mut commands: Commands,
+) {
+    for (entity, controller, grounded, velocity, mass, colliders, mut tracker) in &mut query {
+        let previous_velocity = tracker.previous_velocity;
+        let was_grounded = tracker.was_grounded;
+        tracker.previous_velocity = velocity.0;
+        tracker.was_grounded = grounded.is_grounded();
+
+        let Some(curve) = controller.impact_damage else {
+            continue;
+        };
+
+        if let Some(ground_normal) = grounded.0
+            && !was_grounded
+        {
+            let landing_speed = -previous_velocity.dot(ground_normal);
+            let damage = curve.damage(landing_speed);
+            if damage > 0.0 {
+                debug!("{entity} landed at {landing_speed} m/s");
+                commands.queue(deal_damage(entity, None, DamageKind::Fall, damage));
+            }
+            continue;
+        }
+
+        let mut contacts = vec![];
+        for &collider in colliders.collection() {
+            for pair in collisions.collisions_with(collider) {
+                if !pair.is_touching() {
+                    continue;
+                }
+                let (sign, source) = if pair.collider1 == collider {
+                    (-1.0, pair.body2)
+                } else {
+                    (1.0, pair.body1)
+                };
+                contacts.extend(pair.manifolds.iter().map(|manifold| {
+                    ContactImpulse {
+                        normal: manifold.normal * sign,
+                        impulse: manifold
+                            .points
+                            .iter()
+                            .map(|point| point.normal_impulse)
+                            .sum(),
+                        source,
+                    }
+                }));
+            }
+        }
+
+        let (impulse, source) = impact_from_contacts(&contacts, controller.max_slope_angle);
+        let impact_speed = impulse * mass.inverse();
+        let damage = curve.damage(impact_speed);
+        if damage <= 0.0 {
+            continue;
+        }
+
+        debug!("{entity} hit {source:?} at {impact_speed} m/s");
+        commands.queue(deal_damage(entity, source, DamageKind::Impact, damage));
+    }
+}
+
+/// Pushes character controller `target` by changing its velocity by `velocity`.
+///
+/// The change is not counted as an impact by [`ImpactDamageCurve`], but landing afterwards may
+/// still deal fall damage.
+pub fn knockback(target: Entity, velocity: Vec3) -> impl Command {
+    move |world: &mut World| {
+        let Ok(mut entity) = world.get_entity_mut(target) else {
+            return;
+        };
+        if let Some(mut tracker) = entity.get_mut::<ImpactTracker>() {
+            tracker.previous_velocity += velocity;
+        }
+        if let Some(mut linear_velocity) = entity.get_mut::<LinearVelocity>() {
+            linear_velocity.0 += velocity;
+        }
+    }
+}
+
 /// Responds to [`MovementAction`] events and moves character controllers accordingly.
 fn movement(
     on: On<MovementEvent>,
@@ -197,3 +360,56 @@ fn apply_movement_damping(mut query: Query<(&CharacterController, &mut LinearVel
         linear_velocity.z *= controller.movement_damping_factor;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn impact_damage_starts_at_min_speed() {
+        let curve = ImpactDamageCurve::default();
+        assert_eq!(curve.damage(0.0), 0.0);
+        assert_eq!(curve.damage(curve.min_speed), 0.0);
+        assert!(curve.damage(curve.min_speed + 1.0) > 0.0);
+        assert!(curve.damage(curve.min_speed + 2.0) > curve.damage(curve.min_speed + 1.0));
+
+        let linear = ImpactDamageCurve {
+            min_speed: 10.0,
+            damage_per_speed: 2.0,
+            exponent: 1.0,
+        };
+        assert_eq!(linear.damage(15.0), 10.0);
+    }
+
+    #[test]
+    fn ground_contacts_are_left_to_landing_damage() {
+        let mut world = World::new();
+        let wall = world.spawn_empty().id();
+        let ground = world.spawn_empty().id();
+        let contacts = [
+            ContactImpulse {
+                normal: Vec3::Y,
+                impulse: 100.0,
+                source: Some(ground),
+            },
+            ContactImpulse {
+                normal: Vec3::X,
+                impulse: 30.0,
+                source: Some(wall),
+            },
+            ContactImpulse {
+                normal: Vec3::NEG_Y,
+                impulse: 10.0,
+                source: None,
+            },
+        ];
+
+        let (impulse, source) = impact_from_contacts(&contacts, Some(PI * 0.45));
+        assert_eq!(impulse, 40.0);
+        assert_eq!(source, Some(wall));
+
+        let (impulse, source) = impact_from_contacts(&contacts[..1], Some(PI * 0.45));
+        assert_eq!(impulse, 0.0);
+        assert_eq!(source, None);
+    }
+}
@@ -1,4 +1,5 @@
-use avian3d::prelude::*;
+use std::{borrow::Cow, f32::consts::TAU, time::Duration};
+
 use bevy::{
     ecs::{lifecycle::HookContext, world::DeferredWorld},
     prelude::*,
@@ -7,20 +8,19 @@ use bevy::{
 use crate::{
     character::{
         ai::{
-            ActiveNode, AiActionSystems, AiTarget, BehaviorTreeRoot, LeafNodeResult, SequenceNode,
-            TimeLimitNode,
+            ActiveNode, AiActionSystems, AiBlackboard, AiTarget, BehaviorNode, BehaviorTree,
+            BlackboardKey, LeafNodeResult, NodeChildren, ReflectBehaviorNode,
+            blackboard::{BlackboardValue, HOME_POSITION, TARGET_ENTITY},
         },
-        controller::{CharacterController, MovementEvent, MovementEventKind},
-        health::{DeathEvent, DespawnOnDeath, Health},
-        player::Player,
+        animation::{Animator, CharacterAnimation},
+        controller::knockback,
+        health::{DamageKind, DeathEvent, DespawnOnDeath, Health, deal_damage},
     },
-    dev_util::{
-        debug_annotation::{debug_annot_ui, target::AnnotTargetAabb},
-        mesh_alpha::OverwriteAlpha,
+    item::{
+        ItemRegistry,
+        loot::{LootContext, LootRng, LootTable},
     },
-    item::{ItemId, ItemStack},
-    object::dropped_item::dropped_item_bundle,
-    physics::GameLayer,
+    object::dropped_item::DropPlacer,
 };
 
 pub struct EnemyPlugin;
@@ -29,63 +29,33 @@ impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            (update_sleep_action, chase_action_update).in_set(AiActionSystems::UpdateAction),
+            (
+                update_sleep_action,
+                pick_patrol_point_action_update,
+                melee_attack_action_update,
+            )
+                .in_set(AiActionSystems::UpdateAction),
         )
-        .add_systems(Startup, spawn_enemy);
+        .add_observer(stop_ai_on_death);
     }
 }
 
 #[derive(Component, Clone)]
-#[require(Transform, Visibility, Health::new(100.0), DespawnOnDeath)]
+#[require(
+    Transform,
+    Visibility,
+    Health::new(100.0),
+    DespawnOnDeath::after(DEATH_DURATION)
+)]
 pub struct Enemy;
 
-fn spawn_enemy(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let enemy_base = (
-        Name::new("Enemy"),
-        Enemy,
-        Mass(2.0),
-        Friction::new(0.5),
-        RigidBody::Dynamic,
-        CharacterController {
-            movement_acceleration: 50.0,
-            ..default()
-        },
-        CollisionLayers::new(
-            [GameLayer::Character],
-            [GameLayer::Terrain, GameLayer::Character],
-        ),
-        AnnotTargetAabb,
-        SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset("models/Enemy.glb"))),
-        OverwriteAlpha(0.8),
-        DropItemOnDeath(ItemStack::new(ItemId(257), 3).unwrap()),
-    );
-
-    for i in 0..3 {
-        let position = Vec3::new(15.0 + i as f32 * 5.0, 20.0, 20.0 + i as f32 * 5.0);
-        let mut enemy = commands.spawn((enemy_base.clone(), Transform::from_translation(position)));
-        let id = enemy.id();
-        enemy.with_children(|parent| {
-            parent.spawn((
-                SequenceNode { repeat: true },
-                BehaviorTreeRoot::new(id),
-                ActiveNode,
-                children![
-                    (
-                        TimeLimitNode::from_seconds(10.0),
-                        children![(ChasePlayerAction)],
-                    ),
-                    (SleepAction::from_seconds(5.0)),
-                ],
-            ));
-        });
-
-        commands.spawn(debug_annot_ui(id));
-    }
-}
+/// How long dead enemies stay around, to play the death animation.
+const DEATH_DURATION: Duration = Duration::from_secs(2);
 
+/// Loot dropped where the entity dies.
 #[derive(Component, Clone)]
 #[component(on_add = on_add_drop_item_on_death)]
-struct DropItemOnDeath(ItemStack);
+pub struct DropItemOnDeath(pub LootTable);
 
 fn on_add_drop_item_on_death(mut world: DeferredWorld, context: HookContext) {
     world
@@ -96,94 +66,224 @@ fn on_add_drop_item_on_death(mut world: DeferredWorld, context: HookContext) {
 
 fn drop_item_on_death(
     death: On<DeathEvent>,
-    mut commands: Commands,
+    mut placer: DropPlacer,
     transforms: Query<&GlobalTransform>,
     q: Query<&DropItemOnDeath>,
+    registry: Res<ItemRegistry>,
+    mut rng: ResMut<LootRng>,
 ) -> Result<()> {
     let translation = transforms.get(death.event_target())?.translation();
-    let item_stack = q.get(death.event_target())?.0;
-    commands.spawn((
-        dropped_item_bundle(item_stack)?,
-        // TODO: use shape cast to drop at ground
-        Transform::from_translation(translation + Vec3::Y * 0.5),
-    ));
+    let context = LootContext {
+        cause: Some(death.kind()),
+        tool: None,
+    };
+    let loot = q
+        .get(death.event_target())?
+        .0
+        .roll(&context, &registry, &mut rng.0);
+    placer.drop_items(translation + Vec3::Y * 0.5, loot)?;
     Ok(())
 }
 
-#[derive(Component)]
-struct ChasePlayerAction;
-
-fn chase_action_update(
-    ai_target: AiTarget,
-    mut query: Query<(Entity, &ChasePlayerAction, &mut LeafNodeResult), With<ActiveNode>>,
-    mut transforms: ParamSet<(Query<&Transform, With<Player>>, Query<&mut Transform>)>,
+fn stop_ai_on_death(
+    death: On<DeathEvent>,
+    enemies: Query<(), With<Enemy>>,
     mut commands: Commands,
-) -> Result<()> {
-    let Some(player_translation) = transforms
-        .p0()
-        .iter()
-        .next()
-        .map(|transform| transform.translation)
-    else {
-        return Err("No player found".into());
-    };
-
-    let mut enemy_transforms = transforms.p1();
-
-    for (id, _action, mut result) in &mut query {
-        let target = match ai_target.get_target(id) {
-            Ok(target) => target,
-            Err(e) => {
-                error!("could not get target: {e}");
-                continue;
-            }
-        };
-        let Ok(mut enemy_transform) = enemy_transforms.get_mut(target) else {
-            continue;
-        };
-        let to_player = player_translation - enemy_transform.translation;
-        let mut planar = Vec3::new(to_player.x, 0.0, to_player.z);
+) {
+    if enemies.contains(death.entity()) {
+        commands.entity(death.entity()).remove::<BehaviorTree>();
+    }
+}
 
-        if planar.length_squared() <= 0.5 {
-            debug!("Enemy reached player");
-            result.set_complete();
-            continue;
-        }
+/// Blackboard entry for the position the enemy patrols to.
+const PATROL_POSITION: BlackboardKey<Vec3> = BlackboardKey::new("patrol_position");
 
-        planar = planar.normalize();
-        enemy_transform.rotation = Quat::from_rotation_arc(-Vec3::Z, planar);
+/// Stores a random position within `radius` of the home position to patrol to.
+/// Fails if there is no home position.
+#[derive(Component, Reflect)]
+#[reflect(Component, BehaviorNode)]
+struct PickPatrolPointAction {
+    radius: f32,
+}
 
-        commands.trigger(MovementEvent {
-            entity: target,
-            kind: MovementEventKind::Move(Vec2::Y),
-        });
+impl BehaviorNode for PickPatrolPointAction {
+    const CHILDREN: NodeChildren = NodeChildren::None;
+}
 
-        result.set_continue();
+fn pick_patrol_point_action_update(
+    mut blackboard: AiBlackboard,
+    mut query: Query<(Entity, &PickPatrolPointAction, &mut LeafNodeResult), With<ActiveNode>>,
+) -> Result<()> {
+    for (id, action, mut result) in &mut query {
+        let Some(home) = blackboard.get(id, HOME_POSITION)? else {
+            result.set_failure();
+            continue;
+        };
+        let angle = rand::random::<f32>() * TAU;
+        // Uniform over the disk
+        let distance = rand::random::<f32>().sqrt() * action.radius;
+        let offset = Vec3::new(angle.cos(), 0.0, angle.sin()) * distance;
+        blackboard.set(id, PATROL_POSITION, home + offset)?;
+        result.set_complete();
     }
 
     Ok(())
 }
 
-#[derive(Component)]
-struct SleepAction(Timer);
+/// Does nothing for `duration`, then succeeds.
+#[derive(Component, Reflect)]
+#[reflect(Component, BehaviorNode)]
+pub struct SleepAction {
+    duration: Duration,
+    #[reflect(ignore)]
+    elapsed: Duration,
+}
+
+impl BehaviorNode for SleepAction {
+    const CHILDREN: NodeChildren = NodeChildren::None;
+}
 
 impl SleepAction {
-    fn from_seconds(seconds: f32) -> Self {
-        SleepAction(Timer::from_seconds(seconds, TimerMode::Once))
+    /// Time slept so far, and the total duration.
+    pub fn progress(&self) -> (Duration, Duration) {
+        (self.elapsed, self.duration)
     }
 }
 
 fn update_sleep_action(
-    mut query: Query<(&mut SleepAction, &mut LeafNodeResult), With<ActiveNode>>,
+    mut query: Query<(&mut SleepAction, &mut LeafNodeResult, Ref<ActiveNode>)>,
     time: Res<Time>,
 ) {
-    let delta = time.delta();
-    for (mut sleep, mut result) in &mut query {
-        if sleep.0.tick(delta).just_finished() {
-            sleep.0.reset();
+    for (mut sleep, mut result, active) in &mut query {
+        if active.is_added() {
+            sleep.elapsed = Duration::ZERO;
+        }
+        sleep.elapsed += time.delta();
+        if sleep.elapsed >= sleep.duration {
             result.set_complete();
         } else {
             result.set_continue();
         }
     }
 }
+
+/// Swings at the entity in the blackboard entry `target`: faces it during `wind_up`, hits it
+/// if it is within `range` in front during `hit_window`, then rests for `cooldown`.
+/// Fails if there is no target to swing at, and succeeds otherwise, even if the swing misses.
+#[derive(Component, Reflect)]
+#[reflect(Component, Default, BehaviorNode)]
+struct MeleeAttackAction {
+    target: Cow<'static, str>,
+    range: f32,
+    damage: f32,
+    /// Speed the target is pushed away at.
+    knockback: f32,
+    wind_up: Duration,
+    hit_window: Duration,
+    cooldown: Duration,
+    #[reflect(ignore)]
+    elapsed: Duration,
+    #[reflect(ignore)]
+    hit: bool,
+}
+
+impl BehaviorNode for MeleeAttackAction {
+    const CHILDREN: NodeChildren = NodeChildren::None;
+}
+
+impl Default for MeleeAttackAction {
+    fn default() -> Self {
+        MeleeAttackAction {
+            target: TARGET_ENTITY.name().into(),
+            range: 2.0,
+            damage: 10.0,
+            knockback: 6.0,
+            wind_up: Duration::from_millis(400),
+            hit_window: Duration::from_millis(200),
+            cooldown: Duration::from_millis(800),
+            elapsed: Duration::ZERO,
+            hit: false,
+        }
+    }
+}
+
+/// Cosine of the half angle of the arc in front of the attacker that a swing hits.
+const MELEE_ARC_COS: f32 = 0.5;
+
+fn melee_attack_action_update(
+    ai_target: AiTarget,
+    blackboard: AiBlackboard,
+    mut query: Query<(
+        Entity,
+        &mut MeleeAttackAction,
+        &mut LeafNodeResult,
+        Ref<ActiveNode>,
+    )>,
+    mut transforms: Query<&mut Transform>,
+    mut animators: Query<&mut Animator>,
+    mut commands: Commands,
+    time: Res<Time>,
+) -> Result<()> {
+    for (id, mut attack, mut result, active) in &mut query {
+        let attacker = ai_target.get_target(id)?;
+        if active.is_added() {
+            attack.elapsed = Duration::ZERO;
+            attack.hit = false;
+            if let Ok(mut animator) = animators.get_mut(attacker) {
+                animator.play(CharacterAnimation::Attack);
+            }
+        }
+        attack.elapsed += time.delta();
+
+        let hit_end = attack.wind_up + attack.hit_window;
+        if attack.elapsed >= hit_end + attack.cooldown {
+            result.set_complete();
+            continue;
+        }
+        result.set_continue();
+        if attack.hit || attack.elapsed >= hit_end {
+            continue;
+        }
+
+        let victim = match blackboard.blackboard(id)?.get_value(&attack.target) {
+            Some(&BlackboardValue::Entity(entity)) => transforms
+                .get(entity)
+                .ok()
+                .map(|transform| (entity, transform.translation)),
+            _ => None,
+        };
+        let Some((victim, victim_position)) = victim else {
+            if attack.elapsed < attack.wind_up {
+                debug!("No target `{}` to attack", attack.target);
+                result.set_failure();
+            }
+            continue;
+        };
+
+        let mut transform = transforms.get_mut(attacker)?;
+        let offset = victim_position - transform.translation;
+        let direction = Vec3::new(offset.x, 0.0, offset.z).normalize_or_zero();
+        if attack.elapsed < attack.wind_up {
+            if direction != Vec3::ZERO {
+                transform.rotation = Quat::from_rotation_arc(-Vec3::Z, direction);
+            }
+            continue;
+        }
+
+        if offset.length() > attack.range || transform.forward().dot(direction) < MELEE_ARC_COS {
+            continue;
+        }
+        attack.hit = true;
+        commands.queue(deal_damage(
+            victim,
+            Some(attacker),
+            DamageKind::Melee,
+            attack.damage,
+        ));
+        // Slightly upwards, so that friction doesn't stop it right away
+        let push = (direction + Vec3::Y * 0.5).normalize() * attack.knockback;
+        commands.queue(knockback(victim, push));
+    }
+
+    Ok(())
+}
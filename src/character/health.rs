@@ -1,12 +1,26 @@
 #![allow(dead_code)]
 
+use std::time::Duration;
+
 use bevy::{
     ecs::{lifecycle::HookContext, world::DeferredWorld},
+    platform::collections::HashMap,
     prelude::*,
 };
 
+use serde::Deserialize;
+
+use crate::pause::PausableSystems;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        (tick_invulnerability, regenerate_health, tick_despawn_timers).in_set(PausableSystems),
+    );
+}
+
 /// Add this component to an entity to give it health.
-/// Observe [`DamageEvent`] and [`DeathEvent`] to respond to damage and death.
+/// Observe [`DamageEvent`], [`DeathEvent`] and [`HealEvent`] to respond to damage, death and healing.
 #[derive(Component)]
 pub struct Health {
     pub current: f32,
@@ -20,11 +34,84 @@ impl Health {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum DamageKind {
+    Generic,
+    Explosion,
+    Melee,
+    Fall,
+    /// Collision with another body at high speed.
+    Impact,
+    Fire,
+    Drowning,
+}
+
+impl DamageKind {
+    /// Physical damage is reduced by [`Armor`].
+    pub fn is_physical(self) -> bool {
+        matches!(
+            self,
+            DamageKind::Explosion | DamageKind::Melee | DamageKind::Fall | DamageKind::Impact
+        )
+    }
+}
+
+/// Fraction of incoming damage ignored for each [`DamageKind`].
+/// `1.0` makes the entity immune, negative values make it take extra damage.
+#[derive(Component, Default, Clone, Debug)]
+pub struct Resistances(pub HashMap<DamageKind, f32>);
+
+impl Resistances {
+    pub fn with(mut self, kind: DamageKind, resistance: f32) -> Self {
+        self.0.insert(kind, resistance);
+        self
+    }
+
+    pub fn get(&self, kind: DamageKind) -> f32 {
+        self.0.get(&kind).copied().unwrap_or(0.0)
+    }
+}
+
+/// Reduces physical damage by `armor / (armor + ARMOR_SCALE)`.
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Armor(pub f32);
+
+impl Armor {
+    /// Armor value that halves physical damage.
+    pub const ARMOR_SCALE: f32 = 20.0;
+
+    pub fn reduction(&self) -> f32 {
+        let armor = self.0.max(0.0);
+        armor / (armor + Self::ARMOR_SCALE)
+    }
+}
+
+/// Applies resistances and armor to `amount`.
+fn mitigate_damage(
+    amount: f32,
+    kind: DamageKind,
+    resistances: Option<&Resistances>,
+    armor: Option<&Armor>,
+) -> f32 {
+    let mut amount = amount;
+    if let Some(resistances) = resistances {
+        amount *= 1.0 - resistances.get(kind);
+    }
+    if kind.is_physical()
+        && let Some(armor) = armor
+    {
+        amount *= 1.0 - armor.reduction();
+    }
+    amount.max(0.0)
+}
+
 #[derive(EntityEvent, Debug, Clone, Copy)]
 pub struct DamageEvent {
     entity: Entity,
     source: Option<Entity>,
+    kind: DamageKind,
     amount: f32,
+    raw_amount: f32,
 }
 
 impl DamageEvent {
@@ -39,15 +126,26 @@ impl DamageEvent {
         self.source
     }
 
+    pub fn kind(&self) -> DamageKind {
+        self.kind
+    }
+
+    /// Damage after resistances and armor are applied.
     pub fn amount(&self) -> f32 {
         self.amount
     }
+
+    /// Damage before resistances and armor are applied.
+    pub fn raw_amount(&self) -> f32 {
+        self.raw_amount
+    }
 }
 
 #[derive(EntityEvent, Debug, Clone, Copy)]
 pub struct DeathEvent {
     entity: Entity,
     source: Option<Entity>,
+    kind: DamageKind,
 }
 
 impl DeathEvent {
@@ -58,14 +156,46 @@ impl DeathEvent {
     pub fn source(&self) -> Option<Entity> {
         self.source
     }
+
+    /// Kind of the damage that killed the entity.
+    pub fn kind(&self) -> DamageKind {
+        self.kind
+    }
 }
 
 /// Modifies `Health` component of `target` entity and triggers [`DamageEvent`] or [`DeathEvent`].
-pub fn deal_damage(target: Entity, source: Option<Entity>, amount: f32) -> impl Command {
+///
+/// [`Resistances`] and [`Armor`] of `target` are applied to `amount` before `Health` is modified.
+pub fn deal_damage(
+    target: Entity,
+    source: Option<Entity>,
+    kind: DamageKind,
+    amount: f32,
+) -> impl Command {
     move |world: &mut World| {
-        debug!("Dealing {} damage to {:?}", amount, target);
+        debug!("Dealing {} {:?} damage to {:?}", amount, kind, target);
 
-        let Some(mut health) = world.get_mut::<Health>(target) else {
+        let Ok(mut entity) = world.get_entity_mut(target) else {
+            warn!("Entity {:?} does not exist", target);
+            return;
+        };
+
+        if entity
+            .get::<Invulnerability>()
+            .is_some_and(|inv| inv.is_active())
+        {
+            debug!("{:?} is invulnerable, ignoring damage", target);
+            return;
+        }
+
+        let mitigated = mitigate_damage(
+            amount,
+            kind,
+            entity.get::<Resistances>(),
+            entity.get::<Armor>(),
+        );
+
+        let Some(mut health) = entity.get_mut::<Health>() else {
             warn!("Entity {:?} has no Health component", target);
             return;
         };
@@ -75,28 +205,193 @@ pub fn deal_damage(target: Entity, source: Option<Entity>, amount: f32) -> impl
             return;
         }
 
-        health.current = (health.current - amount).max(0.0);
+        health.current = (health.current - mitigated).max(0.0);
+        let is_dead = health.current == 0.0;
 
-        if health.current == 0.0 {
+        // Fully mitigated hits don't count as taking damage
+        if mitigated > 0.0 {
+            if let Some(mut invulnerability) = entity.get_mut::<Invulnerability>() {
+                invulnerability.start();
+            }
+            if let Some(mut regeneration) = entity.get_mut::<Regeneration>() {
+                regeneration.reset_delay();
+            }
+        }
+
+        if is_dead {
             world.trigger(DeathEvent {
                 entity: target,
                 source,
+                kind,
             });
         } else {
             world.trigger(DamageEvent {
                 entity: target,
                 source,
-                amount,
+                kind,
+                amount: mitigated,
+                raw_amount: amount,
             });
         }
     }
 }
 
-/// Despawns the entity when it receives a `DeathEvent`.
-/// Note that despawn is done in an observer.
+#[derive(EntityEvent, Debug, Clone, Copy)]
+pub struct HealEvent {
+    entity: Entity,
+    source: Option<Entity>,
+    amount: f32,
+}
+
+impl HealEvent {
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    pub fn source(&self) -> Option<Entity> {
+        self.source
+    }
+
+    /// Health actually restored, after clamping to `Health::max`.
+    pub fn amount(&self) -> f32 {
+        self.amount
+    }
+}
+
+/// Modifies `Health` component of `target` entity and triggers [`HealEvent`].
+///
+/// Dead entities are not healed, and no event is triggered if health is already full.
+pub fn heal(target: Entity, source: Option<Entity>, amount: f32) -> impl Command {
+    move |world: &mut World| {
+        let Some(mut health) = world.get_mut::<Health>(target) else {
+            warn!("Entity {:?} has no Health component", target);
+            return;
+        };
+
+        if health.current <= 0.0 || amount <= 0.0 {
+            return;
+        }
+
+        let previous = health.current;
+        health.current = (health.current + amount).min(health.max);
+        let healed = health.current - previous;
+
+        if healed > 0.0 {
+            world.trigger(HealEvent {
+                entity: target,
+                source,
+                amount: healed,
+            });
+        }
+    }
+}
+
+/// Ignores all damage for `duration` after the entity takes damage.
+#[derive(Component, Clone, Debug)]
+pub struct Invulnerability {
+    pub duration: Duration,
+    remaining: Duration,
+}
+
+impl Invulnerability {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            remaining: Duration::ZERO,
+        }
+    }
+
+    pub fn from_seconds(seconds: f32) -> Self {
+        Self::new(Duration::from_secs_f32(seconds))
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.remaining.is_zero()
+    }
+
+    fn start(&mut self) {
+        self.remaining = self.duration;
+    }
+}
+
+fn tick_invulnerability(mut query: Query<&mut Invulnerability>, time: Res<Time>) {
+    for mut invulnerability in &mut query {
+        if invulnerability.is_active() {
+            invulnerability.remaining = invulnerability.remaining.saturating_sub(time.delta());
+        }
+    }
+}
+
+/// Interval between the heals of [`Regeneration`].
+const REGENERATION_TICK: Duration = Duration::from_millis(500);
+
+/// Restores health over time, starting `delay` after the entity last took damage.
+///
+/// Health is restored every [`REGENERATION_TICK`] rather than every frame.
+#[derive(Component, Clone, Debug)]
+pub struct Regeneration {
+    pub per_second: f32,
+    pub delay: Duration,
+    remaining_delay: Duration,
+    tick: Timer,
+}
+
+impl Regeneration {
+    pub fn new(per_second: f32, delay: Duration) -> Self {
+        Self {
+            per_second,
+            delay,
+            remaining_delay: Duration::ZERO,
+            tick: Timer::new(REGENERATION_TICK, TimerMode::Repeating),
+        }
+    }
+
+    fn reset_delay(&mut self) {
+        self.remaining_delay = self.delay;
+        self.tick.reset();
+    }
+}
+
+fn regenerate_health(
+    mut query: Query<(Entity, &mut Regeneration, &Health)>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (entity, mut regeneration, health) in &mut query {
+        if !regeneration.remaining_delay.is_zero() {
+            regeneration.remaining_delay =
+                regeneration.remaining_delay.saturating_sub(time.delta());
+            continue;
+        }
+        if health.current <= 0.0 || health.current >= health.max {
+            regeneration.tick.reset();
+            continue;
+        }
+        let ticks = regeneration
+            .tick
+            .tick(time.delta())
+            .times_finished_this_tick();
+        if ticks > 0 {
+            let amount = regeneration.per_second * REGENERATION_TICK.as_secs_f32() * ticks as f32;
+            commands.queue(heal(entity, None, amount));
+        }
+    }
+}
+
+/// Despawns the entity `delay` after it receives a `DeathEvent`.
+/// Note that despawn is done in an observer when there is no delay.
 #[derive(Component, Default)]
 #[component(on_add = on_add_despawn_on_death)]
-pub struct DespawnOnDeath;
+pub struct DespawnOnDeath {
+    pub delay: Duration,
+}
+
+impl DespawnOnDeath {
+    /// Despawns the entity `delay` after its death, e.g. to play a death animation.
+    pub fn after(delay: Duration) -> Self {
+        Self { delay }
+    }
+}
 
 fn on_add_despawn_on_death(mut world: DeferredWorld, context: HookContext) {
     world
@@ -105,6 +400,243 @@ fn on_add_despawn_on_death(mut world: DeferredWorld, context: HookContext) {
         .observe(despawn_on_death);
 }
 
-fn despawn_on_death(death: On<DeathEvent>, mut commands: Commands) {
-    commands.entity(death.entity()).despawn();
+fn despawn_on_death(death: On<DeathEvent>, query: Query<&DespawnOnDeath>, mut commands: Commands) {
+    let delay = query
+        .get(death.entity())
+        .map_or(Duration::ZERO, |despawn| despawn.delay);
+    if delay.is_zero() {
+        commands.entity(death.entity()).despawn();
+    } else {
+        commands
+            .entity(death.entity())
+            .insert(DespawnTimer(Timer::new(delay, TimerMode::Once)));
+    }
+}
+
+/// Time left before a dead entity is despawned.
+#[derive(Component)]
+struct DespawnTimer(Timer);
+
+fn tick_despawn_timers(
+    mut query: Query<(Entity, &mut DespawnTimer)>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for (entity, mut timer) in &mut query {
+        if timer.0.tick(time.delta()).is_finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Health observed by event observers, in trigger order.
+    #[derive(Resource, Default)]
+    struct Observed(Vec<(&'static str, f32)>);
+
+    fn setup_world() -> World {
+        let mut world = World::new();
+        world.init_resource::<Observed>();
+        world.add_observer(
+            |on: On<DamageEvent>, healths: Query<&Health>, mut observed: ResMut<Observed>| {
+                observed
+                    .0
+                    .push(("damage", healths.get(on.entity()).unwrap().current));
+            },
+        );
+        world.add_observer(
+            |on: On<DeathEvent>, healths: Query<&Health>, mut observed: ResMut<Observed>| {
+                observed
+                    .0
+                    .push(("death", healths.get(on.entity()).unwrap().current));
+            },
+        );
+        world.add_observer(
+            |on: On<HealEvent>, healths: Query<&Health>, mut observed: ResMut<Observed>| {
+                observed
+                    .0
+                    .push(("heal", healths.get(on.entity()).unwrap().current));
+            },
+        );
+        world
+    }
+
+    #[test]
+    fn damage_event_observes_modified_health() {
+        let mut world = setup_world();
+        let entity = world.spawn(Health::new(100.0)).id();
+
+        deal_damage(entity, None, DamageKind::Generic, 30.0).apply(&mut world);
+
+        assert_eq!(world.resource::<Observed>().0, vec![("damage", 70.0)]);
+    }
+
+    #[test]
+    fn lethal_damage_triggers_only_death_once() {
+        let mut world = setup_world();
+        let entity = world.spawn(Health::new(100.0)).id();
+
+        deal_damage(entity, None, DamageKind::Generic, 150.0).apply(&mut world);
+        deal_damage(entity, None, DamageKind::Generic, 10.0).apply(&mut world);
+
+        assert_eq!(world.resource::<Observed>().0, vec![("death", 0.0)]);
+    }
+
+    #[test]
+    fn heal_event_observes_clamped_health() {
+        let mut world = setup_world();
+        let entity = world
+            .spawn(Health {
+                current: 90.0,
+                max: 100.0,
+            })
+            .id();
+        world.add_observer(|on: On<HealEvent>| {
+            assert_eq!(on.amount(), 10.0);
+        });
+
+        heal(entity, None, 25.0).apply(&mut world);
+        // Already full
+        heal(entity, None, 25.0).apply(&mut world);
+
+        assert_eq!(world.resource::<Observed>().0, vec![("heal", 100.0)]);
+    }
+
+    #[test]
+    fn heal_does_not_revive() {
+        let mut world = setup_world();
+        let entity = world.spawn(Health::new(100.0)).id();
+
+        deal_damage(entity, None, DamageKind::Generic, 100.0).apply(&mut world);
+        heal(entity, None, 50.0).apply(&mut world);
+
+        assert_eq!(world.get::<Health>(entity).unwrap().current, 0.0);
+        assert_eq!(world.resource::<Observed>().0, vec![("death", 0.0)]);
+    }
+
+    #[test]
+    fn invulnerability_ignores_damage_until_window_ends() {
+        let mut world = setup_world();
+        world.insert_resource(Time::<()>::default());
+        let entity = world
+            .spawn((Health::new(100.0), Invulnerability::from_seconds(0.5)))
+            .id();
+
+        deal_damage(entity, None, DamageKind::Explosion, 10.0).apply(&mut world);
+        deal_damage(entity, None, DamageKind::Explosion, 10.0).apply(&mut world);
+        assert_eq!(world.get::<Health>(entity).unwrap().current, 90.0);
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(0.6));
+        world.run_system_cached(tick_invulnerability).unwrap();
+
+        deal_damage(entity, None, DamageKind::Explosion, 10.0).apply(&mut world);
+        assert_eq!(
+            world.resource::<Observed>().0,
+            vec![("damage", 90.0), ("damage", 80.0)]
+        );
+    }
+
+    #[test]
+    fn fully_mitigated_damage_does_not_start_invulnerability() {
+        let mut world = setup_world();
+        let entity = world
+            .spawn((
+                Health::new(100.0),
+                Resistances::default().with(DamageKind::Fire, 1.0),
+                Invulnerability::from_seconds(0.5),
+            ))
+            .id();
+
+        deal_damage(entity, None, DamageKind::Fire, 10.0).apply(&mut world);
+        assert!(!world.get::<Invulnerability>(entity).unwrap().is_active());
+
+        deal_damage(entity, None, DamageKind::Generic, 10.0).apply(&mut world);
+        assert!(world.get::<Invulnerability>(entity).unwrap().is_active());
+    }
+
+    #[test]
+    fn regeneration_heals_in_ticks_until_full() {
+        let mut world = setup_world();
+        world.insert_resource(Time::<()>::default());
+        let entity = world
+            .spawn((
+                Health {
+                    current: 97.0,
+                    max: 100.0,
+                },
+                Regeneration::new(4.0, Duration::ZERO),
+            ))
+            .id();
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_millis(300));
+        world.run_system_cached(regenerate_health).unwrap();
+        assert_eq!(world.get::<Health>(entity).unwrap().current, 97.0);
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_millis(300));
+        world.run_system_cached(regenerate_health).unwrap();
+        assert_eq!(world.get::<Health>(entity).unwrap().current, 99.0);
+
+        for _ in 0..4 {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_millis(300));
+            world.run_system_cached(regenerate_health).unwrap();
+        }
+        assert_eq!(
+            world.resource::<Observed>().0,
+            vec![("heal", 99.0), ("heal", 100.0)]
+        );
+    }
+
+    #[test]
+    fn damage_event_reports_mitigated_and_raw_amounts() {
+        let mut world = World::new();
+        let entity = world
+            .spawn((
+                Health::new(100.0),
+                Resistances::default().with(DamageKind::Explosion, 0.5),
+                Armor(Armor::ARMOR_SCALE),
+            ))
+            .id();
+        world.add_observer(|on: On<DamageEvent>| {
+            assert_eq!(on.raw_amount(), 40.0);
+            assert_eq!(on.amount(), 10.0);
+        });
+
+        deal_damage(entity, None, DamageKind::Explosion, 40.0).apply(&mut world);
+
+        assert_eq!(world.get::<Health>(entity).unwrap().current, 90.0);
+    }
+
+    #[test]
+    fn despawn_on_death_waits_for_delay() {
+        let mut world = World::new();
+        world.insert_resource(Time::<()>::default());
+        let entity = world
+            .spawn((
+                Health::new(100.0),
+                DespawnOnDeath::after(Duration::from_secs(1)),
+            ))
+            .id();
+        world.flush();
+
+        deal_damage(entity, None, DamageKind::Generic, 100.0).apply(&mut world);
+        world.flush();
+        assert!(world.get_entity(entity).is_ok());
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(1.1));
+        world.run_system_cached(tick_despawn_timers).unwrap();
+        assert!(world.get_entity(entity).is_err());
+    }
 }
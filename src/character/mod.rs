@@ -1,10 +1,14 @@
 use bevy::prelude::*;
 
-mod ai;
+pub mod ai;
+pub mod animation;
+pub mod archetype;
 pub mod controller;
 pub mod enemy;
 pub mod health;
+pub mod perception;
 pub mod player;
+pub mod spawner;
 
 pub struct CharacterPlugin;
 
@@ -13,6 +17,11 @@ impl Plugin for CharacterPlugin {
         app.add_plugins(player::PlayerPlugin)
             .add_plugins(enemy::EnemyPlugin)
             .add_plugins(ai::AiPlugin)
-            .add_plugins(controller::plugin);
+            .add_plugins(controller::plugin)
+            .add_plugins(health::plugin)
+            .add_plugins(perception::plugin)
+            .add_plugins(animation::plugin)
+            .add_plugins(spawner::plugin)
+            .add_plugins(archetype::plugin);
     }
 }
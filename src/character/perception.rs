@@ -0,0 +1,353 @@
+//! What characters can see and hear, written to the blackboard of their behavior tree.
+
+use std::time::Duration;
+
+use avian3d::prelude::*;
+use bevy::{platform::collections::HashMap, prelude::*};
+
+use crate::{
+    character::{
+        ai::{
+            AiBlackboard, BehaviorTreeRoot,
+            blackboard::{LAST_SEEN_POSITION, NOISE_POSITION, TARGET_ENTITY},
+        },
+        controller::Grounded,
+        player::Player,
+    },
+    explosion::Explode,
+    pause::PausableSystems,
+    terrain::chunk::{BlockRemoved, ReadBlocks},
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_message::<Noise>().add_systems(
+        Update,
+        (
+            (noise_on_explode, noise_on_block_removed, footstep_noise),
+            update_perception,
+        )
+            .chain()
+            .in_set(PausableSystems),
+    );
+}
+
+/// Loudness of an explosion per block of radius.
+const EXPLOSION_LOUDNESS: f32 = 8.0;
+const BLOCK_BREAK_LOUDNESS: f32 = 12.0;
+/// Distance walked between footsteps.
+const STRIDE_LENGTH: f32 = 2.0;
+/// Loudness of a footstep per m/s of speed.
+const FOOTSTEP_LOUDNESS: f32 = 1.0;
+/// Height of the eyes above the character's origin.
+const EYE_HEIGHT: f32 = 0.5;
+
+/// A sound that characters with [`Perception`] can hear.
+#[derive(Message, Debug, Clone, Copy)]
+pub struct Noise {
+    pub position: Vec3,
+    /// Distance at which the noise can be heard.
+    pub loudness: f32,
+    pub source: Option<Entity>,
+}
+
+/// Senses of a character. Players it sees, and noises it hears, are written to the blackboard
+/// of behavior trees targeting it:
+///
+/// - [`TARGET_ENTITY`] is the closest player in sight, and is cleared when it is out of sight.
+/// - [`LAST_SEEN_POSITION`] is where a player was last seen.
+/// - [`NOISE_POSITION`] is where the last noise was heard.
+///
+/// Positions are cleared once they are older than `memory`.
+#[derive(Component, Clone, Debug)]
+#[require(PerceptionMemory)]
+pub struct Perception {
+    pub sight_range: f32,
+    /// Angle of the field of view, in radians.
+    pub field_of_view: f32,
+    /// Multiplier of the loudness of noises.
+    pub hearing: f32,
+    pub memory: Duration,
+}
+
+impl Default for Perception {
+    fn default() -> Self {
+        Perception {
+            sight_range: 24.0,
+            field_of_view: 120f32.to_radians(),
+            hearing: 1.0,
+            memory: Duration::from_secs(10),
+        }
+    }
+}
+
+/// What a character with [`Perception`] currently knows.
+#[derive(Component, Default, Clone, Debug)]
+pub struct PerceptionMemory {
+    /// Player currently in sight.
+    pub visible: Option<Entity>,
+    pub last_seen: Option<(Vec3, Duration)>,
+    pub last_heard: Option<(Vec3, Duration)>,
+}
+
+fn noise_on_explode(mut explosions: MessageReader<Explode>, mut noises: MessageWriter<Noise>) {
+    noises.write_batch(explosions.read().map(|explode| Noise {
+        position: explode.position,
+        loudness: explode.radius * EXPLOSION_LOUDNESS,
+        source: None,
+    }));
+}
+
+fn noise_on_block_removed(
+    mut removed: MessageReader<BlockRemoved>,
+    mut noises: MessageWriter<Noise>,
+) {
+    noises.write_batch(removed.read().map(|&BlockRemoved(position)| Noise {
+        position: position.as_vec3() + Vec3::splat(0.5),
+        loudness: BLOCK_BREAK_LOUDNESS,
+        source: None,
+    }));
+}
+
+/// Distance walked since the last footstep.
+#[derive(Component, Default)]
+pub struct Footsteps(f32);
+
+/// Makes noise as players walk. Faster steps are louder.
+fn footstep_noise(
+    mut players: Query<
+        (
+            Entity,
+            &Transform,
+            &LinearVelocity,
+            &Grounded,
+            &mut Footsteps,
+        ),
+        With<Player>,
+    >,
+    mut noises: MessageWriter<Noise>,
+    time: Res<Time>,
+) {
+    for (entity, transform, velocity, grounded, mut footsteps) in &mut players {
+        if !grounded.is_grounded() {
+            continue;
+        }
+        let speed = Vec2::new(velocity.x, velocity.z).length();
+        footsteps.0 += speed * time.delta_secs();
+        if footsteps.0 >= STRIDE_LENGTH {
+            footsteps.0 = 0.0;
+            noises.write(Noise {
+                position: transform.translation,
+                loudness: speed * FOOTSTEP_LOUDNESS,
+                source: Some(entity),
+            });
+        }
+    }
+}
+
+fn update_perception(
+    mut perceivers: Query<(Entity, &Transform, &Perception, &mut PerceptionMemory)>,
+    players: Query<(Entity, &Transform), With<Player>>,
+    roots: Query<(Entity, &BehaviorTreeRoot)>,
+    mut noises: MessageReader<Noise>,
+    mut blackboard: AiBlackboard,
+    blocks: ReadBlocks,
+    time: Res<Time>,
+) -> Result<()> {
+    let now = time.elapsed();
+    let noises = noises.read().copied().collect::<Vec<_>>();
+    let roots = roots
+        .iter()
+        .map(|(root, tree)| (tree.target, root))
+        .collect::<HashMap<_, _>>();
+
+    for (entity, transform, perception, mut memory) in &mut perceivers {
+        let eye = transform.translation + Vec3::Y * EYE_HEIGHT;
+        let forward = transform.forward().as_vec3();
+
+        let visible = players
+            .iter()
+            .filter_map(|(player, player_transform)| {
+                let to_player = player_transform.translation - eye;
+                let distance = to_player.length();
+                let in_sight = distance <= perception.sight_range
+                    && forward.angle_between(to_player) <= perception.field_of_view / 2.0
+                    && blocks.ray_cast(eye, to_player, distance).is_none();
+                in_sight.then_some((player, player_transform.translation, distance))
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+
+        let heard = noises
+            .iter()
+            .filter(|noise| noise.source != Some(entity))
+            .map(|noise| (noise, noise.position.distance(eye)))
+            .filter(|&(noise, distance)| distance <= noise.loudness * perception.hearing)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(noise, _)| noise.position);
+
+        let previous = memory.visible;
+        memory.visible = visible.map(|(player, _, _)| player);
+        if let Some((_, position, _)) = visible {
+            memory.last_seen = Some((position, now));
+        }
+        if let Some(position) = heard {
+            memory.last_heard = Some((position, now));
+        }
+        let forget = |sensed: &mut Option<(Vec3, Duration)>| {
+            let expired = sensed.is_some_and(|(_, at)| now.saturating_sub(at) > perception.memory);
+            if expired {
+                *sensed = None;
+            }
+            expired
+        };
+        let forget_seen = forget(&mut memory.last_seen);
+        let forget_heard = forget(&mut memory.last_heard);
+
+        let Some(&root) = roots.get(&entity) else {
+            continue;
+        };
+        if let Some((player, position, _)) = visible {
+            blackboard.set(root, TARGET_ENTITY, player)?;
+            blackboard.set(root, LAST_SEEN_POSITION, position)?;
+        } else if let Some(previous) = previous {
+            debug!("{entity} lost sight of {previous}");
+            blackboard.clear(root, TARGET_ENTITY)?;
+        }
+        if let Some(position) = heard {
+            blackboard.set(root, NOISE_POSITION, position)?;
+        }
+        if forget_seen {
+            blackboard.clear(root, LAST_SEEN_POSITION)?;
+        }
+        if forget_heard {
+            blackboard.clear(root, NOISE_POSITION)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        character::ai::{Blackboard, BlackboardChanged},
+        terrain::chunk::ChunkMap,
+    };
+
+    fn setup_world() -> World {
+        let mut world = World::new();
+        world.insert_resource(Time::<()>::default());
+        world.init_resource::<ChunkMap>();
+        world.init_resource::<Messages<Noise>>();
+        world.init_resource::<Messages<BlackboardChanged>>();
+        world
+    }
+
+    /// Spawns a character at `position` looking towards -Z, with a behavior tree.
+    fn spawn_perceiver(world: &mut World, position: Vec3) -> (Entity, Entity) {
+        let entity = world
+            .spawn((Transform::from_translation(position), Perception::default()))
+            .id();
+        let root = world.spawn(BehaviorTreeRoot::new(entity)).id();
+        (entity, root)
+    }
+
+    fn run_perception(world: &mut World) {
+        world
+            .run_system_cached::<Result<()>, _, _>(update_perception)
+            .unwrap()
+            .unwrap();
+    }
+
+    fn blackboard(world: &World, root: Entity) -> &Blackboard {
+        world.get::<Blackboard>(root).unwrap()
+    }
+
+    #[test]
+    fn players_are_seen_inside_the_view_cone_and_range() {
+        let mut world = setup_world();
+        let (entity, root) = spawn_perceiver(&mut world, Vec3::ZERO);
+        let player = world
+            .spawn((Player, Transform::from_xyz(0.0, 0.0, -10.0)))
+            .id();
+
+        run_perception(&mut world);
+        assert_eq!(
+            world.get::<PerceptionMemory>(entity).unwrap().visible,
+            Some(player)
+        );
+        assert_eq!(blackboard(&world, root).get(TARGET_ENTITY), Some(player));
+
+        // Behind
+        world.get_mut::<Transform>(player).unwrap().translation = Vec3::new(0.0, 0.0, 10.0);
+        run_perception(&mut world);
+        assert_eq!(world.get::<PerceptionMemory>(entity).unwrap().visible, None);
+        assert_eq!(blackboard(&world, root).get(TARGET_ENTITY), None);
+
+        // Out of range
+        world.get_mut::<Transform>(player).unwrap().translation = Vec3::new(0.0, 0.0, -30.0);
+        run_perception(&mut world);
+        assert_eq!(world.get::<PerceptionMemory>(entity).unwrap().visible, None);
+    }
+
+    #[test]
+    fn noises_are_heard_within_their_loudness() {
+        let mut world = setup_world();
+        let (near, near_root) = spawn_perceiver(&mut world, Vec3::new(5.0, 0.0, 0.0));
+        let (far, far_root) = spawn_perceiver(&mut world, Vec3::new(20.0, 0.0, 0.0));
+
+        world.write_message(Noise {
+            position: Vec3::Y * EYE_HEIGHT,
+            loudness: 10.0,
+            source: None,
+        });
+        run_perception(&mut world);
+
+        let heard = |entity| {
+            world
+                .get::<PerceptionMemory>(entity)
+                .unwrap()
+                .last_heard
+                .map(|(position, _)| position)
+        };
+        assert_eq!(heard(near), Some(Vec3::Y * EYE_HEIGHT));
+        assert_eq!(heard(far), None);
+        assert_eq!(
+            blackboard(&world, near_root).get(NOISE_POSITION),
+            Some(Vec3::Y * EYE_HEIGHT)
+        );
+        assert_eq!(blackboard(&world, far_root).get(NOISE_POSITION), None);
+    }
+
+    #[test]
+    fn memory_is_cleared_after_it_decays() {
+        let mut world = setup_world();
+        let (_, root) = spawn_perceiver(&mut world, Vec3::ZERO);
+        let player = world
+            .spawn((Player, Transform::from_xyz(0.0, 0.0, -10.0)))
+            .id();
+        world.write_message(Noise {
+            position: Vec3::ZERO,
+            loudness: 10.0,
+            source: None,
+        });
+
+        run_perception(&mut world);
+        assert!(blackboard(&world, root).get(LAST_SEEN_POSITION).is_some());
+        assert!(blackboard(&world, root).get(NOISE_POSITION).is_some());
+
+        world.entity_mut(player).despawn();
+        let memory = Perception::default().memory;
+        world.resource_mut::<Time>().advance_by(memory);
+        run_perception(&mut world);
+        assert!(blackboard(&world, root).get(LAST_SEEN_POSITION).is_some());
+        assert!(blackboard(&world, root).get(NOISE_POSITION).is_some());
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs(1));
+        run_perception(&mut world);
+        assert_eq!(blackboard(&world, root).get(LAST_SEEN_POSITION), None);
+        assert_eq!(blackboard(&world, root).get(NOISE_POSITION), None);
+    }
+}
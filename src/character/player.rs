@@ -7,12 +7,13 @@ use crate::{
     character::{
         controller::{MovementEvent, MovementEventKind},
         health::Health,
+        perception::Footsteps,
     },
     inventory::Inventory,
-    item::ItemRegistry,
-    object::dropped_item::PickupItems,
+    item::{ItemRegistry, ItemStack},
+    object::dropped_item::{DropPlacer, PickupItems},
     pause::PausableSystems,
-    ui::hotbar::Hotbar,
+    ui::{hotbar::Hotbar, inventory::InventoryState},
 };
 
 pub struct PlayerPlugin;
@@ -23,19 +24,31 @@ impl Plugin for PlayerPlugin {
             Update,
             (keyboard_input, gamepad_input).in_set(PausableSystems),
         )
-        .add_systems(Update, player_camera_control.in_set(PausableSystems))
+        .add_systems(
+            Update,
+            player_camera_control
+                .in_set(PausableSystems)
+                // The cursor is free to use the inventory
+                .run_if(in_state(InventoryState::Close)),
+        )
         .add_systems(
             Update,
             use_selected_hotbar_item
                 .in_set(PausableSystems)
                 .run_if(input_just_pressed(MouseButton::Right)),
+        )
+        .add_systems(
+            Update,
+            drop_selected_hotbar_item
+                .in_set(PausableSystems)
+                .run_if(input_just_pressed(KeyCode::KeyG)),
         );
     }
 }
 
 /// Marker for the character to be controlled by the player.
 #[derive(Component)]
-#[require(PickupItems, Health::new(100.0))]
+#[require(PickupItems, Health::new(100.0), Footsteps)]
 pub struct Player;
 
 #[derive(Component)]
@@ -172,3 +185,35 @@ fn use_selected_hotbar_item(
 
     Ok(())
 }
+
+/// Throws one item in the currently selected hotbar slot, or the whole stack while Ctrl is held.
+fn drop_selected_hotbar_item(
+    hotbars: Query<&Hotbar>,
+    mut inventories: Query<(&mut Inventory, Option<&ChildOf>)>,
+    players: Query<(), With<Player>>,
+    camera: Single<&GlobalTransform, With<PlayerCamera>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut placer: DropPlacer,
+) -> Result<()> {
+    let quantity = if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        ItemStack::MAX_QUANTITY
+    } else {
+        1
+    };
+
+    for hotbar in &hotbars {
+        let Ok((mut inventory, Some(&ChildOf(owner)))) = inventories.get_mut(hotbar.inventory)
+        else {
+            continue;
+        };
+        if !players.contains(owner) {
+            continue;
+        }
+
+        if let Some(item_stack) = inventory.take(hotbar.active_slot as usize, quantity) {
+            placer.throw_item(camera.translation(), camera.forward(), item_stack)?;
+        }
+    }
+
+    Ok(())
+}
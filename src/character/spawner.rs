@@ -0,0 +1,326 @@
+//! Spawns enemies around players following [`SpawnRules`], and despawns enemies left far
+//! behind.
+
+use std::{f32::consts::TAU, time::Duration};
+
+use bevy::{platform::collections::HashMap, prelude::*};
+use bevy_common_assets::ron::RonAssetPlugin;
+use rand::{Rng, seq::IndexedRandom};
+use serde::Deserialize;
+
+use crate::{
+    character::{
+        archetype::spawn_enemy,
+        enemy::Enemy,
+        player::{Player, PlayerCamera},
+    },
+    pause::PausableSystems,
+    terrain::{
+        chunk::{CHUNK_HEIGHT, ReadBlocks},
+        pathfinding::{chunk_of, ground_cell},
+    },
+    time_of_day::TimeOfDay,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_plugins(RonAssetPlugin::<SpawnRules>::new(&["spawns.ron"]))
+        .init_resource::<Spawner>()
+        .add_systems(
+            Update,
+            (validate_spawn_rules, despawn_far_enemies, spawn_enemies)
+                .chain()
+                .in_set(PausableSystems),
+        );
+}
+
+/// Where and when enemies spawn, loaded from a `.spawns.ron` file.
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct SpawnRules {
+    /// Seconds between spawn attempts.
+    pub interval: f32,
+    /// Positions tried around each player per attempt.
+    pub attempts: u32,
+    /// Enemies spawn between these horizontal distances from a player.
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Enemies further than this from every player are despawned.
+    pub despawn_distance: f32,
+    pub max_enemies: usize,
+    pub max_enemies_per_chunk: usize,
+    /// Highest light level enemies spawn at.
+    pub max_light: u8,
+    /// Times of day enemies spawn at. Enemies spawn at any time if empty.
+    #[serde(default)]
+    pub times: Vec<TimeRange>,
+    /// Archetypes of the spawned enemies, picked according to their weights.
+    pub archetypes: Vec<SpawnArchetype>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SpawnArchetype {
+    /// Name of the archetype, see [`spawn_enemy`].
+    pub name: String,
+    pub weight: f32,
+}
+
+/// Part of the day, as fractions of the day since midnight. Wraps around midnight if `end` is
+/// before `start`.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct TimeRange {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl TimeRange {
+    pub fn contains(&self, fraction: f32) -> bool {
+        if self.start <= self.end {
+            self.start <= fraction && fraction < self.end
+        } else {
+            fraction >= self.start || fraction < self.end
+        }
+    }
+}
+
+impl SpawnRules {
+    fn allows_time(&self, fraction: f32) -> bool {
+        self.times.is_empty() || self.times.iter().any(|range| range.contains(fraction))
+    }
+
+    /// Checks for values that spawning can't work with.
+    fn validate(&self) -> Result<()> {
+        let distances_valid = 0.0 <= self.min_distance
+            && self.min_distance <= self.max_distance
+            && self.max_distance.is_finite();
+        if !distances_valid {
+            return Err(format!(
+                "expected 0 <= min_distance ({}) <= max_distance ({})",
+                self.min_distance, self.max_distance
+            )
+            .into());
+        }
+        if self.despawn_distance <= self.max_distance {
+            return Err(format!(
+                "expected despawn_distance ({}) > max_distance ({})",
+                self.despawn_distance, self.max_distance
+            )
+            .into());
+        }
+        if !(self.interval > 0.0 && self.interval.is_finite()) || self.attempts == 0 {
+            return Err(format!(
+                "expected a positive interval ({}) and attempts ({})",
+                self.interval, self.attempts
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct Spawner {
+    rules: Handle<SpawnRules>,
+    /// Whether the loaded rules passed [`SpawnRules::validate`].
+    valid: bool,
+    timer: Timer,
+}
+
+impl FromWorld for Spawner {
+    fn from_world(world: &mut World) -> Self {
+        Spawner {
+            rules: world
+                .resource::<AssetServer>()
+                .load("spawns/enemy.spawns.ron"),
+            valid: false,
+            timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Validates the spawn rules each time they are loaded. Invalid rules are skipped until they are
+/// fixed.
+fn validate_spawn_rules(
+    mut events: MessageReader<AssetEvent<SpawnRules>>,
+    rules: Res<Assets<SpawnRules>>,
+    mut spawner: ResMut<Spawner>,
+) {
+    for event in events.read() {
+        if let AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } = *event
+            && id == spawner.rules.id()
+            && let Some(rules) = rules.get(id)
+        {
+            spawner.valid = match rules.validate() {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("Skipping invalid spawn rules: {e}");
+                    false
+                }
+            };
+        }
+    }
+}
+
+fn spawn_enemies(
+    mut spawner: ResMut<Spawner>,
+    rules: Res<Assets<SpawnRules>>,
+    players: Query<&Transform, With<Player>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+    enemies: Query<&Transform, With<Enemy>>,
+    blocks: ReadBlocks,
+    time_of_day: Res<TimeOfDay>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    time: Res<Time>,
+) {
+    let Some(rules) = rules.get(&spawner.rules).filter(|_| spawner.valid) else {
+        return;
+    };
+    spawner
+        .timer
+        .set_duration(Duration::from_secs_f32(rules.interval.max(0.1)));
+    if !spawner.timer.tick(time.delta()).just_finished()
+        || !rules.allows_time(time_of_day.fraction())
+    {
+        return;
+    }
+
+    let mut total = enemies.iter().len();
+    let mut per_chunk = HashMap::<IVec2, usize>::new();
+    for transform in &enemies {
+        *per_chunk
+            .entry(chunk_of(transform.translation.floor().as_ivec3()))
+            .or_default() += 1;
+    }
+
+    let get_block = |pos: IVec3| blocks.get_block(pos).ok().map(|(block, _)| block);
+    let in_view = |position: Vec3| {
+        cameras.iter().any(|(camera, transform)| {
+            camera
+                .world_to_ndc(transform, position)
+                .is_some_and(|ndc| ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0 && ndc.z > 0.0)
+        })
+    };
+
+    let mut rng = rand::rng();
+    for player in &players {
+        if total >= rules.max_enemies {
+            break;
+        }
+
+        for _ in 0..rules.attempts {
+            let angle = rng.random::<f32>() * TAU;
+            let distance = rng.random_range(rules.min_distance..=rules.max_distance);
+            let column = player.translation + Vec3::new(angle.cos(), 0.0, angle.sin()) * distance;
+
+            // Highest cell that can be stood in
+            let top = column.with_y(CHUNK_HEIGHT as f32 - 1.0);
+            let Some(cell) = ground_cell(get_block, top, CHUNK_HEIGHT as i32) else {
+                continue;
+            };
+            if get_block(cell).is_none_or(|block| block.is_liquid()) {
+                continue;
+            }
+            let chunk = chunk_of(cell);
+            if per_chunk.get(&chunk).copied().unwrap_or(0) >= rules.max_enemies_per_chunk {
+                continue;
+            }
+            let position = cell.as_vec3() + Vec3::new(0.5, 1.0, 0.5);
+            if in_view(position) || time_of_day.light_level(&blocks, cell) > rules.max_light {
+                continue;
+            }
+
+            let Ok(archetype) = rules.archetypes.choose_weighted(&mut rng, |a| a.weight) else {
+                warn_once!("No enemy archetype to spawn");
+                return;
+            };
+            debug!("Spawning {} at {cell}", archetype.name);
+            spawn_enemy(&mut commands, &asset_server, &archetype.name, position);
+            total += 1;
+            *per_chunk.entry(chunk).or_default() += 1;
+            break;
+        }
+    }
+}
+
+fn despawn_far_enemies(
+    spawner: Res<Spawner>,
+    rules: Res<Assets<SpawnRules>>,
+    enemies: Query<(Entity, &Transform), With<Enemy>>,
+    players: Query<&Transform, With<Player>>,
+    mut commands: Commands,
+) {
+    let Some(rules) = rules.get(&spawner.rules).filter(|_| spawner.valid) else {
+        return;
+    };
+    if players.is_empty() {
+        return;
+    }
+
+    for (enemy, transform) in &enemies {
+        if players.iter().all(|player| {
+            player.translation.distance(transform.translation) > rules.despawn_distance
+        }) {
+            debug!("Despawning {enemy}, which is too far from players");
+            commands.entity(enemy).try_despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_range_wraps_around_midnight() {
+        let night = TimeRange {
+            start: 0.8,
+            end: 0.2,
+        };
+        assert!(night.contains(0.9));
+        assert!(night.contains(0.1));
+        assert!(!night.contains(0.5));
+
+        let day = TimeRange {
+            start: 0.25,
+            end: 0.75,
+        };
+        assert!(day.contains(0.5));
+        assert!(!day.contains(0.8));
+    }
+
+    #[test]
+    fn spawn_rules_are_validated() {
+        let mut rules = SpawnRules {
+            interval: 5.0,
+            attempts: 8,
+            min_distance: 24.0,
+            max_distance: 48.0,
+            despawn_distance: 96.0,
+            max_enemies: 12,
+            max_enemies_per_chunk: 3,
+            max_light: 7,
+            times: vec![],
+            archetypes: vec![],
+        };
+        assert!(rules.validate().is_ok());
+
+        rules.min_distance = 64.0;
+        assert!(rules.validate().is_err());
+        rules.min_distance = -1.0;
+        assert!(rules.validate().is_err());
+        rules.min_distance = 0.0;
+        rules.max_distance = f32::INFINITY;
+        assert!(rules.validate().is_err());
+
+        rules.max_distance = 96.0;
+        assert!(
+            rules.validate().is_err(),
+            "enemies would despawn right after spawning"
+        );
+        rules.max_distance = 48.0;
+        rules.interval = 0.0;
+        assert!(rules.validate().is_err());
+        rules.interval = 5.0;
+        rules.attempts = 0;
+        assert!(rules.validate().is_err());
+    }
+}
@@ -0,0 +1,306 @@
+//! Panel showing the live behavior tree of an entity, for debugging AI.
+//!
+//! Press F3 to cycle through entities with a [`BehaviorTree`], and to hide the panel after the
+//! last one.
+
+use std::{fmt::Write as _, time::Duration};
+
+use bevy::{color::palettes::css, platform::collections::HashMap, prelude::*};
+
+use crate::character::{
+    ai::{
+        ActiveNode, AiActionSystems, BehaviorTree, Blackboard, LeafNodeResult, NodeOutcome,
+        TimeLimitState, blackboard::BlackboardValue,
+    },
+    enemy::SleepAction,
+};
+
+pub struct BehaviorTreeViewPlugin;
+
+impl Plugin for BehaviorTreeViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BehaviorTreeView>()
+            .add_systems(Startup, setup_panel)
+            .add_systems(Update, select_tree)
+            .add_systems(
+                FixedUpdate,
+                (
+                    record_leaf_results.before(AiActionSystems::PreUpdateAction),
+                    (rebuild_graph, update_graph, update_blackboard)
+                        .chain()
+                        .after(AiActionSystems::PreUpdateAction),
+                ),
+            );
+    }
+}
+
+const FONT_SIZE: f32 = 10.0;
+const ACTIVE_COLOR: Color = Color::srgba(0.2, 0.4, 0.8, 0.6);
+
+#[derive(Resource, Default)]
+struct BehaviorTreeView {
+    /// Entity whose tree is shown.
+    selected: Option<Entity>,
+    /// Root of the tree the graph was built for.
+    shown_root: Option<Entity>,
+    /// Last result other than [`LeafNodeResult::Idle`] of each leaf node in the graph.
+    leaf_results: HashMap<Entity, LeafNodeResult>,
+}
+
+#[derive(Component)]
+#[require(Node)]
+struct BehaviorTreePanel;
+
+#[derive(Component)]
+struct BehaviorTreeTitle;
+
+/// Container of the node graph.
+#[derive(Component)]
+#[require(Node)]
+struct BehaviorTreeGraph;
+
+/// Label of a behavior tree node in the graph.
+#[derive(Component)]
+struct GraphNodeLabel(Entity);
+
+#[derive(Component)]
+struct BlackboardText;
+
+fn setup_panel(mut commands: Commands) {
+    let font = TextFont {
+        font_size: FONT_SIZE,
+        ..default()
+    };
+    commands.spawn((
+        Name::new("Behavior Tree Panel"),
+        BehaviorTreePanel,
+        Node {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            top: Val::Px(0.0),
+            right: Val::Px(0.0),
+            max_height: Val::Percent(80.0),
+            min_width: Val::Px(240.0),
+            padding: UiRect::all(Val::Px(4.0)),
+            row_gap: Val::Px(4.0),
+            flex_direction: FlexDirection::Column,
+            overflow: Overflow::clip(),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        children![
+            (BehaviorTreeTitle, Text::default(), font.clone()),
+            (
+                BehaviorTreeGraph,
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                }
+            ),
+            (BlackboardText, Text::default(), font),
+        ],
+    ));
+}
+
+fn select_tree(
+    key: Res<ButtonInput<KeyCode>>,
+    trees: Query<Entity, With<BehaviorTree>>,
+    mut view: ResMut<BehaviorTreeView>,
+) {
+    if !key.just_pressed(KeyCode::F3) {
+        return;
+    }
+
+    let mut entities = trees.iter().collect::<Vec<_>>();
+    entities.sort();
+    view.selected = match view
+        .selected
+        .and_then(|selected| entities.iter().position(|&e| e == selected))
+    {
+        // None after the last one, to hide the panel
+        Some(index) => entities.get(index + 1).copied(),
+        None => entities.first().copied(),
+    };
+}
+
+/// Records results of leaf nodes before they are reset by the behavior tree update.
+fn record_leaf_results(
+    mut view: ResMut<BehaviorTreeView>,
+    labels: Query<&GraphNodeLabel>,
+    results: Query<&LeafNodeResult>,
+) {
+    for &GraphNodeLabel(node) in &labels {
+        if let Ok(&result) = results.get(node)
+            && result != LeafNodeResult::Idle
+        {
+            view.leaf_results.insert(node, result);
+        }
+    }
+}
+
+/// Builds the graph when another tree is selected, or the selected tree is rebuilt.
+fn rebuild_graph(
+    mut commands: Commands,
+    mut view: ResMut<BehaviorTreeView>,
+    trees: Query<(&BehaviorTree, Option<&Name>)>,
+    children: Query<&Children>,
+    mut panel: Single<&mut Node, With<BehaviorTreePanel>>,
+    graph: Single<Entity, With<BehaviorTreeGraph>>,
+    mut title: Single<&mut Text, With<BehaviorTreeTitle>>,
+) {
+    let selected = view.selected.and_then(|entity| {
+        let (tree, name) = trees.get(entity).ok()?;
+        Some((entity, tree.root()?, name))
+    });
+    let root = selected.map(|(_, root, _)| root);
+    if root == view.shown_root {
+        return;
+    }
+
+    view.shown_root = root;
+    view.leaf_results.clear();
+    commands.entity(*graph).despawn_related::<Children>();
+    panel.display = if root.is_some() {
+        Display::Flex
+    } else {
+        Display::None
+    };
+
+    let Some((entity, root, name)) = selected else {
+        return;
+    };
+    title.0 = match name {
+        Some(name) => format!("{name} ({entity})"),
+        None => format!("{entity}"),
+    };
+    spawn_graph_node(&mut commands, *graph, root, &children);
+}
+
+/// Spawns the label of `node` under `parent`, followed by its children indented.
+fn spawn_graph_node(
+    commands: &mut Commands,
+    parent: Entity,
+    node: Entity,
+    children: &Query<&Children>,
+) {
+    commands.spawn((
+        GraphNodeLabel(node),
+        Text::default(),
+        TextFont {
+            font_size: FONT_SIZE,
+            ..default()
+        },
+        TextColor::default(),
+        BackgroundColor(Color::NONE),
+        ChildOf(parent),
+    ));
+
+    let Ok(node_children) = children.get(node) else {
+        return;
+    };
+    let list = commands
+        .spawn((
+            Node {
+                flex_direction: FlexDirection::Column,
+                margin: UiRect::left(Val::Px(4.0)),
+                padding: UiRect::left(Val::Px(6.0)),
+                border: UiRect::left(Val::Px(1.0)),
+                ..default()
+            },
+            BorderColor::from(Color::srgba(1.0, 1.0, 1.0, 0.4)),
+            ChildOf(parent),
+        ))
+        .id();
+    for &child in node_children {
+        spawn_graph_node(commands, list, child, children);
+    }
+}
+
+fn update_graph(
+    view: Res<BehaviorTreeView>,
+    mut labels: Query<(
+        &GraphNodeLabel,
+        &mut Text,
+        &mut TextColor,
+        &mut BackgroundColor,
+    )>,
+    nodes: Query<(
+        Option<&Name>,
+        Has<ActiveNode>,
+        Option<&NodeOutcome>,
+        Option<&TimeLimitState>,
+        Option<&SleepAction>,
+    )>,
+) {
+    for (&GraphNodeLabel(node), mut text, mut color, mut background) in &mut labels {
+        let Ok((name, active, outcome, time_limit, sleep)) = nodes.get(node) else {
+            continue;
+        };
+
+        let mut label = name.map_or_else(|| node.to_string(), |name| name.to_string());
+        let result = view.leaf_results.get(&node);
+        if let Some(result) = result {
+            write!(label, " [{result:?}]").unwrap();
+        } else if let Some(outcome) = outcome {
+            write!(label, " [{outcome:?}]").unwrap();
+        }
+
+        let timer = time_limit
+            .and_then(|state| state.timer())
+            .map(|timer| (timer.elapsed(), timer.duration()))
+            .or_else(|| sleep.map(SleepAction::progress));
+        if active && let Some((elapsed, duration)) = timer {
+            write!(label, " {}", format_progress(elapsed, duration)).unwrap();
+        }
+
+        text.0 = label;
+        background.0 = if active { ACTIVE_COLOR } else { Color::NONE };
+        color.0 = match (result, outcome) {
+            (Some(LeafNodeResult::Complete), _) | (None, Some(NodeOutcome::Success)) => {
+                css::LIGHT_GREEN.into()
+            }
+            (Some(LeafNodeResult::Failure), _) | (None, Some(NodeOutcome::Failure)) => {
+                css::SALMON.into()
+            }
+            _ => Color::WHITE,
+        };
+    }
+}
+
+fn format_progress(elapsed: Duration, duration: Duration) -> String {
+    format!(
+        "{:.1}/{:.1}s",
+        elapsed.as_secs_f32(),
+        duration.as_secs_f32()
+    )
+}
+
+fn update_blackboard(
+    view: Res<BehaviorTreeView>,
+    blackboards: Query<&Blackboard>,
+    mut text: Single<&mut Text, With<BlackboardText>>,
+) {
+    let Some(blackboard) = view.shown_root.and_then(|root| blackboards.get(root).ok()) else {
+        return;
+    };
+
+    let mut entries = blackboard.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|&(key, _)| key);
+
+    let mut content = String::from("Blackboard");
+    if entries.is_empty() {
+        content.push_str("\n  (empty)");
+    }
+    for (key, value) in entries {
+        match value {
+            BlackboardValue::Entity(entity) => write!(content, "\n  {key}: {entity}"),
+            BlackboardValue::Vec3(v) => {
+                write!(content, "\n  {key}: ({:.1}, {:.1}, {:.1})", v.x, v.y, v.z)
+            }
+            BlackboardValue::Bool(b) => write!(content, "\n  {key}: {b}"),
+            BlackboardValue::Float(f) => write!(content, "\n  {key}: {f:.2}"),
+        }
+        .unwrap();
+    }
+    text.0 = content;
+}
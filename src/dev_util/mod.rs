@@ -2,6 +2,7 @@
 use bevy::prelude::*;
 
 pub mod asset;
+pub mod behavior_tree_view;
 pub mod debug_annotation;
 pub mod debug_entity;
 pub mod log_window;
@@ -12,6 +13,7 @@ pub struct DevUtilPlugin;
 impl Plugin for DevUtilPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_plugins((
+            behavior_tree_view::BehaviorTreeViewPlugin,
             // log_window::LogWindowPlugin,
             // debug_annotation::DebugAnnotPlugin,
         ));
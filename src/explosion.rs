@@ -1,9 +1,9 @@
 use bevy::prelude::*;
 
 use crate::{
-    character::health::{Health, deal_damage},
-    item::ItemStack,
-    object::dropped_item::dropped_item_bundle,
+    character::health::{DamageKind, Health, deal_damage},
+    item::loot::{BlockLoot, LootContext},
+    object::dropped_item::DropPlacer,
     terrain::chunk::WriteBlocks,
 };
 
@@ -79,7 +79,13 @@ fn break_blocks_on_explode(
     mut blocks: WriteBlocks,
     mut commands: Commands,
     assets: Res<ExplosionAssets>,
+    mut loot: BlockLoot,
+    mut placer: DropPlacer,
 ) -> Result<()> {
+    let context = LootContext {
+        cause: Some(DamageKind::Explosion),
+        tool: None,
+    };
     for explode in explode_reader.read() {
         let radius = explode.radius.max(0.1);
         let center = explode.position;
@@ -104,12 +110,10 @@ fn break_blocks_on_explode(
                         continue;
                     }
 
-                    if let Some(block) = blocks.damage_block(block_pos, damage)? {
-                        commands.spawn((
-                            dropped_item_bundle(ItemStack::new(block.as_item_id(), 1)?)?,
-                            Transform::from_translation(block_center),
-                        ));
-                    }
+                    let Some(block) = blocks.damage_block(block_pos, damage)? else {
+                        continue;
+                    };
+                    placer.drop_items(block_center, loot.roll(block, &context))?;
                 }
             }
         }
@@ -139,7 +143,7 @@ fn deal_damage_on_explode(
                 continue;
             }
 
-            commands.queue(deal_damage(entity, None, damage));
+            commands.queue(deal_damage(entity, None, DamageKind::Explosion, damage));
         }
     }
 }
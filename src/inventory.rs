@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::item::ItemStack;
+use crate::item::{ItemRegistry, ItemStack};
 
 #[derive(Component, Debug, Clone)]
 pub struct Inventory {
@@ -10,15 +10,48 @@ pub struct Inventory {
 }
 
 impl Inventory {
+    /// Number of items of `item_stack` that can be added.
+    pub fn room_for(&self, item_stack: &ItemStack, registry: &ItemRegistry) -> u32 {
+        let max_stack = registry.max_stack(item_stack.item_id);
+        self.slots
+            .iter()
+            .map(|slot| match slot {
+                None => max_stack,
+                Some(stack) if stack.stacks_with(item_stack) => {
+                    max_stack.saturating_sub(stack.quantity())
+                }
+                Some(_) => 0,
+            })
+            .sum()
+    }
+
+    /// Removes up to `quantity` items from the slot at `index`, and returns them.
+    pub fn take(&mut self, index: usize, quantity: u32) -> Option<ItemStack> {
+        let stack = self.slots.get_mut(index)?.as_mut()?;
+        if quantity < stack.quantity() {
+            stack.set_quantity(stack.quantity() - quantity).ok()?;
+            return stack.with_quantity(quantity).ok();
+        }
+        self.slots[index].take()
+    }
+
     /// Returns `Err(remaining)` if there is not enough space.
-    pub fn add_item_stack(&mut self, item_stack: ItemStack) -> Result<(), ItemStack> {
+    pub fn add_item_stack(
+        &mut self,
+        item_stack: ItemStack,
+        registry: &ItemRegistry,
+    ) -> Result<(), ItemStack> {
+        let max_stack = registry.max_stack(item_stack.item_id);
         let mut remaining = item_stack.quantity();
         for slot in self.slots.iter_mut() {
             if let Some(existing_stack) = slot
-                && existing_stack.item_id == item_stack.item_id
+                && existing_stack.stacks_with(&item_stack)
             {
-                let can_add = ItemStack::MAX_QUANTITY - existing_stack.quantity();
+                let can_add = max_stack.saturating_sub(existing_stack.quantity());
                 let to_add = remaining.min(can_add);
+                if to_add == 0 {
+                    continue;
+                }
                 existing_stack
                     .set_quantity(existing_stack.quantity() + to_add)
                     .unwrap();
@@ -30,16 +63,110 @@ impl Inventory {
         }
         for slot in self.slots.iter_mut() {
             if slot.is_none() {
-                // If the slot is empty, we can put all remaining items here
-                // since `remaining <= MAX_QUANTITY`.
-                *slot = Some(ItemStack::new(item_stack.item_id, remaining).unwrap());
-                return Ok(());
+                // `0 < to_add <= max_stack <= MAX_QUANTITY`
+                let to_add = remaining.min(max_stack);
+                *slot = Some(item_stack.with_quantity(to_add).unwrap());
+                remaining -= to_add;
+                if remaining == 0 {
+                    return Ok(());
+                }
             }
         }
-        if remaining > 0 {
-            Err(ItemStack::new(item_stack.item_id, remaining).unwrap())
-        } else {
-            Ok(())
-        }
+        Err(item_stack.with_quantity(remaining).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::{Item, ItemData, ItemId};
+
+    fn stack(id: u32, quantity: u32) -> ItemStack {
+        ItemStack::new(ItemId(id), quantity).unwrap()
+    }
+
+    #[test]
+    fn room_counts_partial_and_empty_slots() {
+        let registry = ItemRegistry::default();
+        let mut inventory = Inventory {
+            slots: vec![None; 3],
+            hotbar: None,
+        };
+        assert_eq!(
+            inventory.room_for(&stack(1, 1), &registry),
+            3 * ItemStack::MAX_QUANTITY
+        );
+
+        inventory.slots[0] = Some(stack(1, 60));
+        inventory.slots[1] = Some(stack(2, 1));
+        assert_eq!(
+            inventory.room_for(&stack(1, 1), &registry),
+            4 + ItemStack::MAX_QUANTITY
+        );
+        assert_eq!(
+            inventory.room_for(&stack(2, 1), &registry),
+            63 + ItemStack::MAX_QUANTITY
+        );
+
+        inventory.slots[2] = Some(stack(2, 64));
+        assert_eq!(inventory.room_for(&stack(1, 1), &registry), 4);
+    }
+
+    #[test]
+    fn take_splits_or_empties_slot() {
+        let mut inventory = Inventory {
+            slots: vec![Some(stack(1, 10)), None],
+            hotbar: None,
+        };
+
+        assert_eq!(inventory.take(0, 3), Some(stack(1, 3)));
+        assert_eq!(inventory.slots[0], Some(stack(1, 7)));
+
+        assert_eq!(inventory.take(0, 64), Some(stack(1, 7)));
+        assert!(inventory.slots[0].is_none());
+        assert!(inventory.take(0, 1).is_none());
+        assert!(inventory.take(5, 1).is_none());
+    }
+
+    struct Pebble;
+
+    impl Item for Pebble {
+        const NAME: &'static str = "Pebble";
+        const MAX_STACK: u32 = 16;
+    }
+
+    #[test]
+    fn stacks_respect_max_stack_and_data() {
+        let mut registry = ItemRegistry::default();
+        registry.register_item::<Pebble>(ItemId(300), Handle::default());
+        let mut inventory = Inventory {
+            slots: vec![None; 3],
+            hotbar: None,
+        };
+
+        let pebbles = ItemStack::new(ItemId(300), 20).unwrap();
+        assert!(inventory.add_item_stack(pebbles, &registry).is_ok());
+        let quantities = inventory
+            .slots
+            .iter()
+            .map(|slot| slot.as_ref().map(ItemStack::quantity))
+            .collect::<Vec<_>>();
+        assert_eq!(quantities, [Some(16), Some(4), None]);
+
+        // Does not stack with the named pebbles
+        let named = ItemStack::new(ItemId(300), 16)
+            .unwrap()
+            .with_data(ItemData {
+                custom_name: Some("Lucky".into()),
+                ..default()
+            });
+        assert_eq!(inventory.room_for(&named, &registry), 16);
+        let remaining = inventory.add_item_stack(named.clone(), &registry);
+        assert!(remaining.is_ok());
+        assert_eq!(inventory.slots[2], Some(named));
+
+        let remaining =
+            inventory.add_item_stack(ItemStack::new(ItemId(300), 20).unwrap(), &registry);
+        assert_eq!(remaining.unwrap_err().quantity(), 8);
     }
 }
@@ -10,6 +10,9 @@ pub struct BoneItem;
 
 impl Item for BoneItem {
     const USABLE: bool = false;
+    const NAME: &'static str = "Bone";
+    const DESCRIPTION: &'static str = "Dropped by enemies.";
+    const TAGS: &'static [&'static str] = &["material"];
 }
 
 fn register_items(mut registry: ResMut<ItemRegistry>, asset_server: Res<AssetServer>) {
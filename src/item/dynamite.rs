@@ -16,6 +16,10 @@ pub struct DynamiteItem;
 
 impl Item for DynamiteItem {
     const USABLE: bool = true;
+    const NAME: &'static str = "Dynamite";
+    const DESCRIPTION: &'static str = "Thrown on use, and explodes shortly after.";
+    const MAX_STACK: u32 = 16;
+    const TAGS: &'static [&'static str] = &["explosive"];
 }
 
 fn register_items(mut registry: ResMut<ItemRegistry>, asset_server: Res<AssetServer>) {
@@ -0,0 +1,373 @@
+//! Weighted tables of items dropped by enemies and broken blocks.
+//!
+//! ```ron
+//! (
+//!     rolls: 1,
+//!     entries: [
+//!         (item: Some(257), count: (1, 3), conditions: [Not(KilledBy(Explosion))]),
+//!         // Drops nothing
+//!         (weight: 2.0),
+//!     ],
+//! )
+//! ```
+//!
+//! Blocks drop according to `loot/blocks.loot.ron`, and drop themselves if they have no table
+//! there.
+
+use bevy::{ecs::system::SystemParam, platform::collections::HashMap, prelude::*};
+use bevy_common_assets::ron::RonAssetPlugin;
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::IndexedRandom};
+use serde::Deserialize;
+
+use crate::{
+    character::health::DamageKind,
+    item::{ItemId, ItemRegistry, ItemStack},
+    terrain::chunk::BlockId,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_plugins(RonAssetPlugin::<BlockLootTables>::new(&["loot.ron"]))
+        .init_resource::<LootRng>()
+        .init_resource::<BlockLootHandle>();
+}
+
+/// Picks [`LootEntry`]s by weight `rolls` times.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LootTable {
+    pub rolls: u32,
+    pub entries: Vec<LootEntry>,
+}
+
+impl Default for LootTable {
+    fn default() -> Self {
+        LootTable {
+            rolls: 1,
+            entries: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LootEntry {
+    /// Item to drop. Entries without an item drop nothing when picked.
+    pub item: Option<ItemId>,
+    pub weight: f32,
+    /// Minimum and maximum quantity dropped, inclusive.
+    pub count: (u32, u32),
+    /// The entry is only picked if all of these hold.
+    pub conditions: Vec<LootCondition>,
+}
+
+impl Default for LootEntry {
+    fn default() -> Self {
+        LootEntry {
+            item: None,
+            weight: 1.0,
+            count: (1, 1),
+            conditions: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum LootCondition {
+    /// Killed or broken by damage of this kind.
+    KilledBy(DamageKind),
+    /// Killed or broken with any item in hand.
+    ToolUsed,
+    /// Killed or broken with this item in hand.
+    Tool(ItemId),
+    Not(Box<LootCondition>),
+}
+
+/// How the loot was obtained, checked against [`LootCondition`]s.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LootContext {
+    /// Kind of the damage that killed the entity or broke the block.
+    pub cause: Option<DamageKind>,
+    /// Item held by whoever killed the entity or broke the block.
+    pub tool: Option<ItemId>,
+}
+
+impl LootCondition {
+    pub fn holds(&self, context: &LootContext) -> bool {
+        match self {
+            LootCondition::KilledBy(kind) => context.cause == Some(*kind),
+            LootCondition::ToolUsed => context.tool.is_some(),
+            LootCondition::Tool(item) => context.tool == Some(*item),
+            LootCondition::Not(condition) => !condition.holds(context),
+        }
+    }
+}
+
+impl LootTable {
+    /// Table always dropping one `item`.
+    pub fn single(item: ItemId) -> Self {
+        LootTable {
+            rolls: 1,
+            entries: vec![LootEntry {
+                item: Some(item),
+                ..default()
+            }],
+        }
+    }
+
+    /// Picks the dropped items. Quantities of the same item are added up and split into full
+    /// stacks.
+    pub fn roll(
+        &self,
+        context: &LootContext,
+        registry: &ItemRegistry,
+        rng: &mut impl Rng,
+    ) -> Vec<ItemStack> {
+        let entries = self
+            .entries
+            .iter()
+            .filter(|entry| entry.conditions.iter().all(|c| c.holds(context)))
+            .collect::<Vec<_>>();
+
+        let mut quantities = Vec::<(ItemId, u32)>::new();
+        for _ in 0..self.rolls {
+            let Ok(entry) = entries.choose_weighted(rng, |entry| entry.weight.max(0.0)) else {
+                break;
+            };
+            let Some(item) = entry.item else {
+                continue;
+            };
+            let (min, max) = entry.count;
+            let quantity = rng.random_range(min..=max.max(min));
+            match quantities.iter_mut().find(|(id, _)| *id == item) {
+                Some((_, total)) => *total += quantity,
+                None => quantities.push((item, quantity)),
+            }
+        }
+
+        let mut stacks = Vec::new();
+        for (item, mut remaining) in quantities {
+            let max_stack = registry.max_stack(item);
+            while remaining > 0 {
+                let quantity = remaining.min(max_stack);
+                // `0 < quantity <= max_stack <= MAX_QUANTITY`
+                stacks.push(ItemStack::new(item, quantity).unwrap());
+                remaining -= quantity;
+            }
+        }
+        stacks
+    }
+}
+
+/// Random number generator for loot.
+#[derive(Resource)]
+pub struct LootRng(pub StdRng);
+
+impl Default for LootRng {
+    fn default() -> Self {
+        LootRng(StdRng::from_os_rng())
+    }
+}
+
+#[cfg(test)]
+impl LootRng {
+    /// Generator giving the same drops for the same `seed`.
+    pub fn seeded(seed: u64) -> Self {
+        LootRng(StdRng::seed_from_u64(seed))
+    }
+}
+
+/// Loot tables of blocks by [`BlockId`], loaded from a `.loot.ron` file.
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct BlockLootTables(pub HashMap<u8, LootTable>);
+
+#[derive(Resource)]
+struct BlockLootHandle(Handle<BlockLootTables>);
+
+impl FromWorld for BlockLootHandle {
+    fn from_world(world: &mut World) -> Self {
+        BlockLootHandle(world.resource::<AssetServer>().load("loot/blocks.loot.ron"))
+    }
+}
+
+/// Rolls the loot of broken blocks.
+#[derive(SystemParam)]
+pub struct BlockLoot<'w> {
+    tables: Res<'w, Assets<BlockLootTables>>,
+    handle: Res<'w, BlockLootHandle>,
+    registry: Res<'w, ItemRegistry>,
+    rng: ResMut<'w, LootRng>,
+}
+
+impl BlockLoot<'_> {
+    /// Items dropped by breaking `block`.
+    pub fn roll(&mut self, block: BlockId, context: &LootContext) -> Vec<ItemStack> {
+        let tables = self.tables.get(&self.handle.0);
+        match tables.and_then(|tables| tables.0.get(&block.0)) {
+            Some(table) => table.roll(context, &self.registry, &mut self.rng.0),
+            None => {
+                LootTable::single(block.as_item_id()).roll(context, &self.registry, &mut self.rng.0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BONE: ItemId = ItemId(257);
+    const DYNAMITE: ItemId = ItemId(256);
+
+    fn entry(item: ItemId, weight: f32) -> LootEntry {
+        LootEntry {
+            item: Some(item),
+            weight,
+            ..default()
+        }
+    }
+
+    fn roll(table: &LootTable, context: &LootContext, rng: &mut StdRng) -> Vec<ItemStack> {
+        table.roll(context, &ItemRegistry::default(), rng)
+    }
+
+    fn count(stacks: &[ItemStack], item: ItemId) -> u32 {
+        stacks
+            .iter()
+            .filter(|stack| stack.item_id == item)
+            .map(ItemStack::quantity)
+            .sum()
+    }
+
+    #[test]
+    fn entries_are_picked_by_weight() {
+        let table = LootTable {
+            rolls: 1,
+            entries: vec![
+                entry(BONE, 3.0),
+                entry(DYNAMITE, 1.0),
+                LootEntry {
+                    weight: 4.0,
+                    ..default()
+                },
+            ],
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let rounds = 10_000;
+        let (mut bones, mut dynamites) = (0, 0);
+        for _ in 0..rounds {
+            let stacks = roll(&table, &LootContext::default(), &mut rng);
+            bones += count(&stacks, BONE);
+            dynamites += count(&stacks, DYNAMITE);
+        }
+        let bone_rate = bones as f32 / rounds as f32;
+        let dynamite_rate = dynamites as f32 / rounds as f32;
+        assert!((bone_rate - 0.375).abs() < 0.02, "{bone_rate}");
+        assert!((dynamite_rate - 0.125).abs() < 0.02, "{dynamite_rate}");
+    }
+
+    #[test]
+    fn quantities_cover_the_range() {
+        let table = LootTable {
+            rolls: 1,
+            entries: vec![LootEntry {
+                count: (2, 5),
+                ..entry(BONE, 1.0)
+            }],
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut seen = [0; 6];
+        for _ in 0..1000 {
+            let quantity = count(&roll(&table, &LootContext::default(), &mut rng), BONE);
+            assert!((2..=5).contains(&quantity));
+            seen[quantity as usize] += 1;
+        }
+        // Each quantity is expected 250 times
+        assert!(seen[2..].iter().all(|&n| n > 200), "{seen:?}");
+    }
+
+    #[test]
+    fn conditions_filter_entries() {
+        let table = LootTable {
+            rolls: 1,
+            entries: vec![
+                LootEntry {
+                    conditions: vec![LootCondition::Not(Box::new(LootCondition::KilledBy(
+                        DamageKind::Explosion,
+                    )))],
+                    ..entry(BONE, 1.0)
+                },
+                LootEntry {
+                    conditions: vec![
+                        LootCondition::KilledBy(DamageKind::Explosion),
+                        LootCondition::Tool(DYNAMITE),
+                    ],
+                    ..entry(DYNAMITE, 1.0)
+                },
+            ],
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let melee = LootContext {
+            cause: Some(DamageKind::Melee),
+            tool: Some(DYNAMITE),
+        };
+        let stacks = roll(&table, &melee, &mut rng);
+        assert_eq!((count(&stacks, BONE), count(&stacks, DYNAMITE)), (1, 0));
+
+        let explosion = LootContext {
+            cause: Some(DamageKind::Explosion),
+            tool: Some(DYNAMITE),
+        };
+        let stacks = roll(&table, &explosion, &mut rng);
+        assert_eq!((count(&stacks, BONE), count(&stacks, DYNAMITE)), (0, 1));
+
+        let no_tool = LootContext {
+            cause: Some(DamageKind::Explosion),
+            tool: None,
+        };
+        assert!(roll(&table, &no_tool, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn rolls_add_up_into_full_stacks() {
+        let table = LootTable {
+            rolls: 5,
+            entries: vec![LootEntry {
+                count: (20, 20),
+                ..entry(BONE, 1.0)
+            }],
+        };
+        let stacks = roll(
+            &table,
+            &LootContext::default(),
+            &mut StdRng::seed_from_u64(0),
+        );
+        let quantities = stacks.iter().map(ItemStack::quantity).collect::<Vec<_>>();
+        assert_eq!(quantities, [64, 36]);
+    }
+
+    #[test]
+    fn same_seed_gives_same_loot() {
+        let table = LootTable {
+            rolls: 3,
+            entries: vec![
+                LootEntry {
+                    count: (1, 10),
+                    ..entry(BONE, 1.0)
+                },
+                entry(DYNAMITE, 1.0),
+            ],
+        };
+        let roll = |seed| {
+            let stacks = roll(
+                &table,
+                &LootContext::default(),
+                &mut LootRng::seeded(seed).0,
+            );
+            (count(&stacks, BONE), count(&stacks, DYNAMITE))
+        };
+        assert_eq!(roll(42), roll(42));
+    }
+}
@@ -1,5 +1,8 @@
 pub mod bone;
 pub mod dynamite;
+pub mod loot;
+pub mod recipe;
+pub mod tool;
 
 use std::marker::PhantomData;
 
@@ -7,13 +10,20 @@ use bevy::{
     platform::collections::{HashMap, HashSet},
     prelude::*,
 };
+use serde::Deserialize;
 
 pub struct ItemPlugin;
 
 impl Plugin for ItemPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ItemRegistry>()
-            .add_plugins((bone::plugin, dynamite::plugin))
+            .add_plugins((
+                bone::plugin,
+                dynamite::plugin,
+                loot::plugin,
+                recipe::plugin,
+                tool::plugin,
+            ))
             .add_systems(
                 PreUpdate,
                 trigger_item_images_added_event.run_if(resource_changed::<ItemRegistry>),
@@ -21,7 +31,8 @@ impl Plugin for ItemPlugin {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(transparent)]
 pub struct ItemId(pub u32);
 
 impl ItemId {
@@ -30,22 +41,56 @@ impl ItemId {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ItemStack {
     /// `item_id < 256` represents blocks
     pub item_id: ItemId,
     /// `0 < quantity <= MAX_QUANTITY`
     quantity: u32,
+    /// Data of this particular stack. Stacks with different data do not stack together.
+    pub data: Option<ItemData>,
+}
+
+/// Per-instance data of an item.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ItemData {
+    pub durability: Option<u32>,
+    pub custom_name: Option<String>,
+    pub charges: Option<u32>,
 }
 
 impl ItemStack {
+    /// Largest quantity of any stack. See [`ItemRegistry::max_stack`] for the limit of each item.
     pub const MAX_QUANTITY: u32 = 64;
 
     pub fn new(item_id: ItemId, quantity: u32) -> Result<ItemStack> {
         if quantity == 0 || quantity > Self::MAX_QUANTITY {
             return Err("ItemStack quantity must be between 1 and MAX_QUANTITY".into());
         }
-        Ok(Self { item_id, quantity })
+        Ok(Self {
+            item_id,
+            quantity,
+            data: None,
+        })
+    }
+
+    // TODO: give data to items outside of tests
+    #[cfg_attr(not(test), expect(dead_code))]
+    pub fn with_data(mut self, data: ItemData) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// Whether items of `other` can be added to this stack.
+    pub fn stacks_with(&self, other: &ItemStack) -> bool {
+        self.item_id == other.item_id && self.data == other.data
+    }
+
+    /// Copy of this stack with a different quantity.
+    pub fn with_quantity(&self, quantity: u32) -> Result<ItemStack> {
+        let mut stack = self.clone();
+        stack.set_quantity(quantity)?;
+        Ok(stack)
     }
 
     pub fn quantity(&self) -> u32 {
@@ -67,11 +112,31 @@ pub struct ItemRegistry {
     /// This is for type erasure.
     on_use: HashMap<ItemId, fn(&mut Commands, Entity)>,
     pub images: HashMap<ItemId, Handle<Image>>,
+    info: HashMap<ItemId, ItemInfo>,
+    tools: HashMap<ItemId, tool::ToolStats>,
 }
 
 /// ID type for items.
 pub trait Item: Sync + Send + 'static {
     const USABLE: bool = false;
+    /// Display name.
+    const NAME: &'static str;
+    const DESCRIPTION: &'static str = "";
+    /// Largest quantity of a stack, at most [`ItemStack::MAX_QUANTITY`].
+    const MAX_STACK: u32 = ItemStack::MAX_QUANTITY;
+    const TAGS: &'static [&'static str] = &[];
+}
+
+/// Metadata of a registered item, taken from its [`Item`] implementation.
+#[derive(Debug, Clone)]
+pub struct ItemInfo {
+    // TODO: show name and description in the inventory
+    #[expect(dead_code)]
+    pub name: &'static str,
+    #[expect(dead_code)]
+    pub description: &'static str,
+    pub max_stack: u32,
+    pub tags: &'static [&'static str],
 }
 
 impl ItemRegistry {
@@ -83,6 +148,36 @@ impl ItemRegistry {
                 });
         }
         self.images.insert(item_id, image);
+        self.info.insert(
+            item_id,
+            ItemInfo {
+                name: T::NAME,
+                description: T::DESCRIPTION,
+                max_stack: T::MAX_STACK.clamp(1, ItemStack::MAX_QUANTITY),
+                tags: T::TAGS,
+            },
+        );
+    }
+
+    /// Metadata of a registered item. Blocks are not registered.
+    #[expect(dead_code)]
+    pub fn info(&self, item_id: ItemId) -> Option<&ItemInfo> {
+        self.info.get(&item_id)
+    }
+
+    /// Largest quantity of a stack of `item_id`. Unregistered items stack up to
+    /// [`ItemStack::MAX_QUANTITY`].
+    pub fn max_stack(&self, item_id: ItemId) -> u32 {
+        self.info
+            .get(&item_id)
+            .map_or(ItemStack::MAX_QUANTITY, |info| info.max_stack)
+    }
+
+    #[expect(dead_code)]
+    pub fn has_tag(&self, item_id: ItemId, tag: &str) -> bool {
+        self.info
+            .get(&item_id)
+            .is_some_and(|info| info.tags.contains(&tag))
     }
 
     /// Returns true if the item is usable.
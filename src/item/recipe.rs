@@ -0,0 +1,303 @@
+//! Crafting recipes, matched against a grid of items.
+//!
+//! ```ron
+//! [
+//!     // Items placed in this shape anywhere in the grid
+//!     Shaped(
+//!         pattern: ["##", "#|"],
+//!         key: {'#': 2, '|': 257},
+//!         output: (item: 258),
+//!     ),
+//!     // Items placed anywhere in the grid
+//!     Shapeless(ingredients: [257, 257], output: (item: 2, count: 4)),
+//! ]
+//! ```
+//!
+//! Spaces in patterns are empty cells. Crafting takes one item from each cell of the grid.
+
+use bevy::{ecs::system::SystemParam, platform::collections::HashMap, prelude::*};
+use bevy_common_assets::ron::RonAssetPlugin;
+use serde::Deserialize;
+
+use crate::item::{ItemId, ItemRegistry, ItemStack};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_plugins(RonAssetPlugin::<Recipes>::new(&["recipes.ron"]))
+        .init_resource::<RecipesHandle>();
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum Recipe {
+    Shaped {
+        /// Rows of the shape, with a character of `key` or a space for each cell.
+        pattern: Vec<String>,
+        key: HashMap<char, ItemId>,
+        output: RecipeOutput,
+    },
+    Shapeless {
+        ingredients: Vec<ItemId>,
+        output: RecipeOutput,
+    },
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct RecipeOutput {
+    pub item: ItemId,
+    #[serde(default = "default_count")]
+    pub count: u32,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+impl Recipe {
+    pub fn output(&self) -> &RecipeOutput {
+        match self {
+            Recipe::Shaped { output, .. } | Recipe::Shapeless { output, .. } => output,
+        }
+    }
+
+    /// Stack crafted by the recipe. The count is capped at the max stack of the item.
+    pub fn output_stack(&self, registry: &ItemRegistry) -> Result<ItemStack> {
+        let output = self.output();
+        ItemStack::new(
+            output.item,
+            output.count.clamp(1, registry.max_stack(output.item)),
+        )
+    }
+
+    /// Whether `grid`, given row by row with rows of `width` cells, holds the ingredients of the
+    /// recipe and nothing else.
+    pub fn matches(&self, grid: &[Option<ItemId>], width: usize) -> bool {
+        if grid.iter().all(Option::is_none) {
+            return false;
+        }
+
+        match self {
+            Recipe::Shaped { pattern, key, .. } => {
+                let pattern_width = pattern.iter().map(|row| row.chars().count()).max();
+                let Some(pattern_width) = pattern_width.filter(|&w| w > 0) else {
+                    return false;
+                };
+                let mut cells = Vec::with_capacity(pattern.len() * pattern_width);
+                for row in pattern {
+                    let mut chars = row.chars();
+                    for _ in 0..pattern_width {
+                        match chars.next().unwrap_or(' ') {
+                            ' ' => cells.push(None),
+                            c => match key.get(&c) {
+                                Some(&item) => cells.push(Some(item)),
+                                None => {
+                                    warn_once!("Recipe pattern has character {c:?} not in key");
+                                    return false;
+                                }
+                            },
+                        }
+                    }
+                }
+                trim(&cells, pattern_width) == trim(grid, width)
+            }
+            Recipe::Shapeless { ingredients, .. } => {
+                let mut items = grid.iter().flatten().copied().collect::<Vec<_>>();
+                let mut ingredients = ingredients.clone();
+                items.sort_by_key(|item| item.0);
+                ingredients.sort_by_key(|item| item.0);
+                items == ingredients
+            }
+        }
+    }
+}
+
+/// Cells of `grid` inside the bounding box of its items, and the width of the box.
+fn trim(grid: &[Option<ItemId>], width: usize) -> (Vec<Option<ItemId>>, usize) {
+    let width = width.max(1);
+    let filled = grid
+        .iter()
+        .enumerate()
+        .filter(|(_, cell)| cell.is_some())
+        .map(|(i, _)| (i / width, i % width))
+        .collect::<Vec<_>>();
+    let rows = filled.iter().map(|&(row, _)| row);
+    let columns = filled.iter().map(|&(_, column)| column);
+    let (Some(top), Some(bottom), Some(left), Some(right)) = (
+        rows.clone().min(),
+        rows.max(),
+        columns.clone().min(),
+        columns.max(),
+    ) else {
+        return (Vec::new(), 0);
+    };
+
+    let cells = (top..=bottom)
+        .flat_map(|r| (left..=right).map(move |c| grid[r * width + c]))
+        .collect();
+    (cells, right - left + 1)
+}
+
+/// Takes one item from each cell of `grid`, as crafting does.
+pub fn consume_ingredients(grid: &mut [Option<ItemStack>]) -> Result<()> {
+    for cell in grid {
+        if let Some(stack) = cell {
+            if stack.quantity() == 1 {
+                *cell = None;
+            } else {
+                stack.set_quantity(stack.quantity() - 1)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Recipes loaded from a `.recipes.ron` file.
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct Recipes(pub Vec<Recipe>);
+
+impl Recipes {
+    /// First recipe matching `grid`, see [`Recipe::matches`].
+    pub fn find(&self, grid: &[Option<ItemStack>], width: usize) -> Option<&Recipe> {
+        let items = grid
+            .iter()
+            .map(|cell| cell.as_ref().map(|stack| stack.item_id))
+            .collect::<Vec<_>>();
+        self.0.iter().find(|recipe| recipe.matches(&items, width))
+    }
+}
+
+#[derive(Resource)]
+struct RecipesHandle(Handle<Recipes>);
+
+impl FromWorld for RecipesHandle {
+    fn from_world(world: &mut World) -> Self {
+        RecipesHandle(
+            world
+                .resource::<AssetServer>()
+                .load("recipes/default.recipes.ron"),
+        )
+    }
+}
+
+/// Finds the recipes of crafting grids.
+#[derive(SystemParam)]
+pub struct RecipeBook<'w> {
+    recipes: Res<'w, Assets<Recipes>>,
+    handle: Res<'w, RecipesHandle>,
+}
+
+impl RecipeBook<'_> {
+    /// First recipe matching `grid`, or `None` if there is none or the recipes are not loaded.
+    pub fn find(&self, grid: &[Option<ItemStack>], width: usize) -> Option<&Recipe> {
+        self.recipes.get(&self.handle.0)?.find(grid, width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: ItemId = ItemId(2);
+    const BONE: ItemId = ItemId(257);
+    const PICKAXE: ItemId = ItemId(258);
+
+    fn pickaxe_recipe() -> Recipe {
+        Recipe::Shaped {
+            pattern: vec!["sss".into(), " b ".into(), " b ".into()],
+            key: HashMap::from([('s', STONE), ('b', BONE)]),
+            output: RecipeOutput {
+                item: PICKAXE,
+                count: 1,
+            },
+        }
+    }
+
+    fn grid(cells: [Option<ItemId>; 9]) -> Vec<Option<ItemId>> {
+        cells.to_vec()
+    }
+
+    #[test]
+    fn shaped_recipes_match_their_pattern() {
+        let (s, b) = (Some(STONE), Some(BONE));
+        let recipe = pickaxe_recipe();
+        assert!(recipe.matches(&grid([s, s, s, None, b, None, None, b, None]), 3));
+        // Wrong shape
+        assert!(!recipe.matches(&grid([s, s, s, b, None, None, b, None, None]), 3));
+        // Extra item
+        assert!(!recipe.matches(&grid([s, s, s, s, b, None, None, b, None]), 3));
+        assert!(!recipe.matches(&[None; 9], 3));
+    }
+
+    #[test]
+    fn shaped_recipes_match_anywhere_in_the_grid() {
+        let (s, b) = (Some(STONE), Some(BONE));
+        let recipe = Recipe::Shaped {
+            pattern: vec!["s".into(), "b".into()],
+            key: HashMap::from([('s', STONE), ('b', BONE)]),
+            output: RecipeOutput {
+                item: PICKAXE,
+                count: 1,
+            },
+        };
+        assert!(recipe.matches(&grid([s, None, None, b, None, None, None, None, None]), 3));
+        assert!(recipe.matches(&grid([None, None, None, None, None, s, None, None, b]), 3));
+        assert!(!recipe.matches(&grid([None, None, None, s, None, None, None, b, None]), 3));
+        // In a larger grid
+        let mut large = vec![None; 16];
+        large[6] = s;
+        large[10] = b;
+        assert!(recipe.matches(&large, 4));
+    }
+
+    #[test]
+    fn shapeless_recipes_ignore_positions() {
+        let recipe = Recipe::Shapeless {
+            ingredients: vec![BONE, STONE, BONE],
+            output: RecipeOutput {
+                item: PICKAXE,
+                count: 1,
+            },
+        };
+        let (s, b) = (Some(STONE), Some(BONE));
+        assert!(recipe.matches(&grid([b, None, None, None, s, None, None, None, b]), 3));
+        assert!(recipe.matches(&grid([s, b, b, None, None, None, None, None, None]), 3));
+        assert!(!recipe.matches(&grid([s, b, None, None, None, None, None, None, None]), 3));
+        assert!(!recipe.matches(&grid([s, b, b, s, None, None, None, None, None]), 3));
+    }
+
+    #[test]
+    fn first_matching_recipe_is_found() {
+        let stacks = |cells: [Option<ItemId>; 9]| {
+            cells.map(|cell| cell.map(|item| ItemStack::new(item, 3).unwrap()))
+        };
+        let recipes = Recipes(vec![
+            Recipe::Shapeless {
+                ingredients: vec![STONE],
+                output: RecipeOutput {
+                    item: BONE,
+                    count: 2,
+                },
+            },
+            pickaxe_recipe(),
+        ]);
+        let (s, b) = (Some(STONE), Some(BONE));
+
+        let found = recipes.find(&stacks([s, s, s, None, b, None, None, b, None]), 3);
+        assert_eq!(found.map(|recipe| recipe.output().item), Some(PICKAXE));
+        let found = recipes.find(
+            &stacks([None, None, None, None, s, None, None, None, None]),
+            3,
+        );
+        assert_eq!(found.map(|recipe| recipe.output().item), Some(BONE));
+        assert!(recipes.find(&stacks([b; 9]), 3).is_none());
+    }
+
+    #[test]
+    fn crafting_takes_one_item_per_cell() {
+        let mut grid = [
+            Some(ItemStack::new(STONE, 3).unwrap()),
+            None,
+            Some(ItemStack::new(BONE, 1).unwrap()),
+        ];
+        consume_ingredients(&mut grid).unwrap();
+        assert_eq!(grid, [Some(ItemStack::new(STONE, 2).unwrap()), None, None]);
+    }
+}
@@ -0,0 +1,227 @@
+//! Tools, which mine blocks faster and harvest harder blocks while held in the active hotbar
+//! slot, and break after [`ToolStats::max_durability`] uses.
+
+use bevy::prelude::*;
+
+use crate::{
+    item::{Item, ItemId, ItemRegistry, ItemStack},
+    terrain::chunk::BlockId,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(Startup, register_items);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolKind {
+    Pickaxe,
+    Shovel,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ToolStats {
+    pub kind: ToolKind,
+    /// Multiplier of the damage dealt to blocks mined with tools of `kind`.
+    pub speed: f32,
+    /// Blocks requiring up to this harvest level drop items when mined with the tool.
+    pub harvest_level: u8,
+    /// Uses before the tool breaks.
+    pub max_durability: u32,
+}
+
+/// An [`Item`] that is a tool. Register with [`ItemRegistry::register_tool`].
+pub trait Tool: Item {
+    const STATS: ToolStats;
+}
+
+impl ItemRegistry {
+    pub fn register_tool<T: Tool>(&mut self, item_id: ItemId, image: Handle<Image>) {
+        self.register_item::<T>(item_id, image);
+        self.tools.insert(item_id, T::STATS);
+    }
+
+    pub fn tool(&self, item_id: ItemId) -> Option<&ToolStats> {
+        self.tools.get(&item_id)
+    }
+
+    /// Remaining durability of `item_stack` as a fraction, or `None` if it is not a tool.
+    pub fn durability_fraction(&self, item_stack: &ItemStack) -> Option<f32> {
+        let stats = self.tool(item_stack.item_id)?;
+        Some(stats.durability(item_stack) as f32 / stats.max_durability.max(1) as f32)
+    }
+}
+
+impl ToolStats {
+    /// Remaining uses of `item_stack`. Tools without durability data are new.
+    pub fn durability(&self, item_stack: &ItemStack) -> u32 {
+        item_stack
+            .data
+            .as_ref()
+            .and_then(|data| data.durability)
+            .unwrap_or(self.max_durability)
+    }
+
+    /// Uses up one durability of `item_stack`. Returns `true` if the tool broke.
+    pub fn wear(&self, item_stack: &mut ItemStack) -> bool {
+        let durability = self.durability(item_stack).saturating_sub(1);
+        item_stack.data.get_or_insert_default().durability = Some(durability);
+        durability == 0
+    }
+}
+
+/// How a block is mined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockMining {
+    /// Divides the damage dealt to the block.
+    pub hardness: f32,
+    /// Kind of tools mining the block faster.
+    pub tool: Option<ToolKind>,
+    /// Lowest [`ToolStats::harvest_level`] for the block to drop items. `0` can be mined
+    /// bare-handed.
+    pub harvest_level: u8,
+}
+
+/// Damage dealt to a block of hardness `1.0` per bare-handed hit. Blocks are broken at `1.0`.
+const HAND_DAMAGE: f32 = 0.5;
+
+impl BlockMining {
+    pub fn of(block: BlockId) -> Self {
+        match block {
+            // Grass
+            BlockId(1) => BlockMining {
+                hardness: 1.0,
+                tool: Some(ToolKind::Shovel),
+                harvest_level: 0,
+            },
+            // Stone
+            BlockId(2) => BlockMining {
+                hardness: 2.0,
+                tool: Some(ToolKind::Pickaxe),
+                harvest_level: 1,
+            },
+            _ => BlockMining {
+                hardness: 1.0,
+                tool: None,
+                harvest_level: 0,
+            },
+        }
+    }
+
+    /// Damage dealt to the block per hit with `tool`, or bare-handed if `None`.
+    pub fn damage(&self, tool: Option<&ToolStats>) -> f32 {
+        let speed = match tool {
+            Some(tool) if self.tool == Some(tool.kind) => tool.speed,
+            _ => 1.0,
+        };
+        HAND_DAMAGE * speed / self.hardness.max(f32::EPSILON)
+    }
+
+    /// Whether the block drops items when mined with `tool`, or bare-handed if `None`.
+    pub fn harvestable(&self, tool: Option<&ToolStats>) -> bool {
+        let level = match tool {
+            Some(tool) if self.tool.is_none_or(|kind| kind == tool.kind) => tool.harvest_level,
+            _ => 0,
+        };
+        level >= self.harvest_level
+    }
+}
+
+pub struct PickaxeItem;
+
+impl Item for PickaxeItem {
+    const NAME: &'static str = "Pickaxe";
+    const DESCRIPTION: &'static str = "Mines stone.";
+    const MAX_STACK: u32 = 1;
+    const TAGS: &'static [&'static str] = &["tool"];
+}
+
+impl Tool for PickaxeItem {
+    const STATS: ToolStats = ToolStats {
+        kind: ToolKind::Pickaxe,
+        speed: 4.0,
+        harvest_level: 1,
+        max_durability: 128,
+    };
+}
+
+pub struct ShovelItem;
+
+impl Item for ShovelItem {
+    const NAME: &'static str = "Shovel";
+    const DESCRIPTION: &'static str = "Digs grass.";
+    const MAX_STACK: u32 = 1;
+    const TAGS: &'static [&'static str] = &["tool"];
+}
+
+impl Tool for ShovelItem {
+    const STATS: ToolStats = ToolStats {
+        kind: ToolKind::Shovel,
+        speed: 4.0,
+        harvest_level: 1,
+        max_durability: 96,
+    };
+}
+
+fn register_items(mut registry: ResMut<ItemRegistry>, asset_server: Res<AssetServer>) {
+    registry
+        .register_tool::<PickaxeItem>(ItemId(258), asset_server.load("textures/items/pickaxe.png"));
+    registry
+        .register_tool::<ShovelItem>(ItemId(259), asset_server.load("textures/items/shovel.png"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: BlockId = BlockId(2);
+
+    #[test]
+    fn matching_tools_mine_faster() {
+        let stone = BlockMining::of(STONE);
+        let hand = stone.damage(None);
+        assert_eq!(stone.damage(Some(&ShovelItem::STATS)), hand);
+        assert_eq!(stone.damage(Some(&PickaxeItem::STATS)), hand * 4.0);
+        assert!(stone.damage(Some(&PickaxeItem::STATS)) >= 1.0);
+    }
+
+    #[test]
+    fn harvest_level_decides_drops() {
+        let stone = BlockMining::of(STONE);
+        assert!(!stone.harvestable(None));
+        assert!(!stone.harvestable(Some(&ShovelItem::STATS)));
+        assert!(stone.harvestable(Some(&PickaxeItem::STATS)));
+
+        let grass = BlockMining::of(BlockId(1));
+        assert!(grass.harvestable(None));
+        assert!(grass.harvestable(Some(&PickaxeItem::STATS)));
+    }
+
+    #[test]
+    fn tools_break_when_worn_out() {
+        let stats = ToolStats {
+            max_durability: 3,
+            ..PickaxeItem::STATS
+        };
+        let mut pickaxe = ItemStack::new(ItemId(258), 1).unwrap();
+        assert_eq!(stats.durability(&pickaxe), 3);
+
+        assert!(!stats.wear(&mut pickaxe));
+        assert!(!stats.wear(&mut pickaxe));
+        assert_eq!(stats.durability(&pickaxe), 1);
+        assert!(stats.wear(&mut pickaxe));
+    }
+
+    #[test]
+    fn worn_tools_do_not_stack_with_new_ones() {
+        let mut registry = ItemRegistry::default();
+        registry.register_tool::<PickaxeItem>(ItemId(258), Handle::default());
+        let new = ItemStack::new(ItemId(258), 1).unwrap();
+        let mut worn = new.clone();
+        PickaxeItem::STATS.wear(&mut worn);
+
+        assert!(!worn.stacks_with(&new));
+        assert_eq!(registry.durability_fraction(&new), Some(1.0));
+        assert_eq!(registry.durability_fraction(&worn), Some(127.0 / 128.0));
+        assert_eq!(registry.max_stack(ItemId(258)), 1);
+    }
+}
@@ -37,8 +37,10 @@ use crate::{
         chunk::{BlockId, Chunk, ChunkPlugin, ChunkUpdated},
         edit::EditPlugin,
         render::RenderPlugin,
+        support::SupportPlugin,
     },
-    ui::UiPlugin,
+    time_of_day::TimeOfDayPlugin,
+    ui::{UiPlugin, inventory::InventoryState},
 };
 
 mod character;
@@ -50,6 +52,7 @@ mod object;
 mod pause;
 mod physics;
 mod terrain;
+mod time_of_day;
 mod ui;
 
 const PLAYER_INVENTORY_SIZE: usize = 36;
@@ -68,10 +71,12 @@ fn main() {
         .add_plugins(ChunkPlugin)
         .add_plugins(RenderPlugin)
         .add_plugins(EditPlugin)
+        .add_plugins(SupportPlugin)
         .add_plugins(CharacterPlugin)
         .add_plugins(ItemPlugin)
         .add_plugins(ObjectPlugin)
         .add_plugins(ExplosionPlugin)
+        .add_plugins(TimeOfDayPlugin)
         .add_plugins(UiPlugin)
         .add_plugins(DevUtilPlugin)
         .add_plugins(FpsOverlayPlugin::default())
@@ -200,6 +205,8 @@ fn spawn_player(
     slots[0] = ItemStack::new(ItemId(1), 64).unwrap().into();
     slots[1] = ItemStack::new(ItemId(2), 32).unwrap().into();
     slots[2] = ItemStack::new(ItemId(256), 16).unwrap().into();
+    slots[3] = ItemStack::new(ItemId(258), 1).unwrap().into();
+    slots[4] = ItemStack::new(ItemId(259), 1).unwrap().into();
     let inventory_id = commands
         .spawn((
             Name::new("Player Inventory Data"),
@@ -219,19 +226,21 @@ fn spawn_player(
 fn mouse_grabbing(
     mut cursor_opt: Query<(&mut CursorOptions, &mut Window), With<PrimaryWindow>>,
     paused: Res<State<Pause>>,
+    inventory: Res<State<InventoryState>>,
 ) -> Result<()> {
     let (mut cursor_opt, mut window) = cursor_opt.single_mut()?;
     if !window.is_changed() {
         return Ok(());
     }
 
-    let (grab_mode, visible) = if paused.0 || !window.focused {
-        (CursorGrabMode::None, true)
-    } else {
-        let pos = Vec2::new(window.width() / 2.0, window.height() / 2.0);
-        window.set_cursor_position(Some(pos));
-        (CursorGrabMode::Locked, false)
-    };
+    let (grab_mode, visible) =
+        if paused.0 || !window.focused || *inventory.get() == InventoryState::Open {
+            (CursorGrabMode::None, true)
+        } else {
+            let pos = Vec2::new(window.width() / 2.0, window.height() / 2.0);
+            window.set_cursor_position(Some(pos));
+            (CursorGrabMode::Locked, false)
+        };
 
     cursor_opt.grab_mode = grab_mode;
     cursor_opt.visible = visible;
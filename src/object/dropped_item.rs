@@ -1,6 +1,8 @@
+use std::{cmp::Reverse, f32::consts::PI, time::Duration};
+
 use avian3d::prelude::*;
 use bevy::{
-    ecs::{entity::EntityHashSet, relationship::RelatedSpawner},
+    ecs::{entity::EntityHashMap, system::SystemParam},
     platform::collections::HashMap,
     prelude::*,
 };
@@ -17,77 +19,100 @@ pub struct DroppedItemPlugin;
 impl Plugin for DroppedItemPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<DroppedItemAssets>()
+            .init_resource::<DroppedItemSettings>()
             .add_observer(add_item_texture)
-            .add_systems(Update, (merge_items, pickup_items).in_set(PausableSystems))
+            .add_systems(
+                Update,
+                (
+                    tick_pickup_delays,
+                    attract_items,
+                    merge_items,
+                    // Before items are despawned
+                    update_item_visuals,
+                    pickup_items,
+                    age_dropped_items,
+                )
+                    .chain()
+                    .in_set(PausableSystems),
+            )
             .add_systems(Update, animate_dropped_items.in_set(PausableSystems));
     }
 }
 
 #[derive(Component)]
-#[require(Visibility, Transform)]
+#[require(Visibility, Transform, ItemAge)]
 pub struct DroppedItem {
     item_stack: ItemStack,
 }
 
+/// How dropped items despawn and are picked up.
+#[derive(Resource, Clone, Debug)]
+pub struct DroppedItemSettings {
+    /// Dropped items despawn after this long.
+    pub lifetime: Duration,
+    /// Items blink for this long before they despawn.
+    pub blink_duration: Duration,
+    /// Items within this distance of a character that can pick them up are pulled toward it.
+    pub magnet_radius: f32,
+    /// Speed of items pulled toward a character.
+    pub magnet_speed: f32,
+}
+
+impl Default for DroppedItemSettings {
+    fn default() -> Self {
+        DroppedItemSettings {
+            lifetime: Duration::from_secs(5 * 60),
+            blink_duration: Duration::from_secs(10),
+            magnet_radius: 3.0,
+            magnet_speed: 6.0,
+        }
+    }
+}
+
+/// Time since the item was dropped.
+#[derive(Component, Default)]
+struct ItemAge(Duration);
+
+/// Items with this component are not picked up or attracted until the timer finishes, e.g. so
+/// that thrown items are not picked up again by the thrower right away.
+#[derive(Component, Clone, Debug)]
+pub struct PickupDelay(pub Timer);
+
+impl PickupDelay {
+    pub fn new(delay: Duration) -> Self {
+        PickupDelay(Timer::new(delay, TimerMode::Once))
+    }
+}
+
+impl Default for PickupDelay {
+    fn default() -> Self {
+        PickupDelay::new(Duration::from_millis(1500))
+    }
+}
+
 #[derive(Component)]
 struct ItemSensor;
 
-pub fn dropped_item_bundle(item_stack: ItemStack) -> Result<impl Bundle> {
-    let item_id = item_stack.item_id;
+/// Radius of the collider of dropped items.
+const ITEM_RADIUS: f32 = 0.2;
 
+/// Mesh showing a dropped item, rebuilt by [`update_item_visuals`] when its quantity changes.
+#[derive(Component)]
+struct ItemVisual;
+
+fn dropped_item_bundle(item_stack: ItemStack) -> Result<impl Bundle> {
     if item_stack.quantity() == 0 {
         return Err("Cannot create DroppedItem with quantity 0".into());
     }
 
-    let num_cubes = (item_stack.quantity() as f32).log2().ceil() as u32 + 1;
-
-    // Visual representation of the dropped item
-    let visual_spawner = if item_id.is_block() {
-        Box::new(move |parent: &mut RelatedSpawner<ChildOf>| {
-            let assets = parent.world().resource::<DroppedItemAssets>();
-            let cloned_mesh = assets.block_mesh.clone();
-            let cloned_material = assets
-                .material_map
-                .get(&item_id)
-                .cloned()
-                .unwrap_or_default();
-
-            for i in 0..num_cubes {
-                let offset = Vec3::new(
-                    (rand::random::<f32>() - 0.5) * 0.2,
-                    (rand::random::<f32>() - 0.5) * 0.2,
-                    (rand::random::<f32>() - 0.5) * 0.2,
-                );
-                parent.spawn((
-                    Transform::from_translation(offset + Vec3::Y * (i as f32 * 0.05)),
-                    Mesh3d(cloned_mesh.clone()),
-                    MeshMaterial3d(cloned_material.clone()),
-                ));
-            }
-        }) as Box<dyn Fn(&mut RelatedSpawner<ChildOf>) + Send + Sync>
-    } else {
-        Box::new(move |parent: &mut RelatedSpawner<ChildOf>| {
-            let assets = parent.world().resource::<DroppedItemAssets>();
-            parent.spawn((
-                Mesh3d(assets.item_mesh.clone()),
-                MeshMaterial3d(
-                    assets
-                        .material_map
-                        .get(&item_id)
-                        .cloned()
-                        .unwrap_or_default(),
-                ),
-            ));
-        })
-    };
-
-    // Sensor to detect collisions for merging and pickup
+    // Sensor to detect characters picking up the item
     let sensor = (
         Name::new("DroppedItem Sensor"),
         ItemSensor,
         Sphere::new(0.5).collider(),
         Sensor,
         CollisionEventsEnabled,
+        CollidingEntities::default(),
         CollisionLayers::new(
             [GameLayer::Object],
             [GameLayer::Terrain, GameLayer::Character, GameLayer::Object],
@@ -95,19 +120,150 @@ pub fn dropped_item_bundle(item_stack: ItemStack) -> Result<impl Bundle> {
     );
 
     Ok((
-        Name::new(format!("DroppedItem ({:?})", item_stack)),
         DroppedItem { item_stack },
-        Sphere::new(0.2).collider(),
+        Sphere::new(ITEM_RADIUS).collider(),
         CollisionLayers::new(
             [GameLayer::Object],
             [GameLayer::Terrain, GameLayer::Character],
         ),
         RigidBody::Dynamic,
         LockedAxes::ROTATION_LOCKED,
-        Children::spawn((Spawn(sensor), SpawnWith(visual_spawner))),
+        children![sensor],
     ))
 }
 
+/// Distance between items dropped together.
+const DROP_SPREAD: f32 = 0.4;
+/// How far above the drop position free space is searched for.
+const MAX_DROP_RISE: f32 = 2.0;
+/// How far below the drop position the ground is searched for. Items dropped higher fall.
+const MAX_DROP_FALL: f32 = 8.0;
+/// Angle between consecutive items in the spiral they are spread on.
+const GOLDEN_ANGLE: f32 = PI * 0.763_932;
+/// Distance in front of the eye thrown items appear at.
+const THROW_DISTANCE: f32 = 0.6;
+/// Initial speed of thrown items.
+const THROW_SPEED: f32 = 6.0;
+/// Upward speed added to thrown items, so that they fly in an arc.
+const THROW_LIFT: f32 = 1.5;
+
+/// Spawns dropped items on the ground, out of terrain and spread so that they do not overlap.
+#[derive(SystemParam)]
+pub struct DropPlacer<'w, 's> {
+    spatial_query: SpatialQuery<'w, 's>,
+    commands: Commands<'w, 's>,
+}
+
+impl DropPlacer<'_, '_> {
+    /// Drops `item_stacks` around `origin`, and returns the spawned items in the same order.
+    pub fn drop_items(
+        &mut self,
+        origin: Vec3,
+        item_stacks: impl IntoIterator<Item = ItemStack>,
+    ) -> Result<Vec<Entity>> {
+        let item_stacks = item_stacks.into_iter().collect::<Vec<_>>();
+        let positions = self.placements(origin, item_stacks.len());
+        let mut items = Vec::with_capacity(item_stacks.len());
+        for (item_stack, position) in item_stacks.into_iter().zip(positions) {
+            let item = self.commands.spawn((
+                dropped_item_bundle(item_stack)?,
+                Transform::from_translation(position),
+            ));
+            items.push(item.id());
+        }
+        Ok(items)
+    }
+
+    /// Throws `item_stack` from `eye` toward `direction`. The item can not be picked up until
+    /// its [`PickupDelay`] finishes.
+    pub fn throw_item(
+        &mut self,
+        eye: Vec3,
+        direction: Dir3,
+        item_stack: ItemStack,
+    ) -> Result<Entity> {
+        let shape = Collider::sphere(ITEM_RADIUS);
+        let filter = SpatialQueryFilter::from_mask(GameLayer::Terrain);
+        // Stop at walls in front of the eye
+        let distance = self
+            .spatial_query
+            .cast_shape(
+                &shape,
+                eye,
+                Quat::IDENTITY,
+                direction,
+                &ShapeCastConfig::from_max_distance(THROW_DISTANCE),
+                &filter,
+            )
+            .map_or(THROW_DISTANCE, |hit| hit.distance);
+        let position = eye + direction * distance;
+        let item = self.commands.spawn((
+            dropped_item_bundle(item_stack)?,
+            Transform::from_translation(position),
+            LinearVelocity(direction * THROW_SPEED + Vec3::Y * THROW_LIFT),
+            PickupDelay::default(),
+        ));
+        Ok(item.id())
+    }
+
+    /// Positions of `count` items dropped at `origin`, spread on a spiral around the first free
+    /// position above it and moved down to the ground.
+    fn placements(&self, origin: Vec3, count: usize) -> Vec<Vec3> {
+        let shape = Collider::sphere(ITEM_RADIUS);
+        let filter = SpatialQueryFilter::from_mask(GameLayer::Terrain);
+        let center = self.free_position(&shape, &filter, origin);
+
+        (0..count)
+            .map(|i| {
+                let mut position = center;
+                if i > 0 {
+                    let direction = Quat::from_rotation_y(i as f32 * GOLDEN_ANGLE) * Dir3::X;
+                    let distance = DROP_SPREAD * (i as f32).sqrt();
+                    // Stop at walls
+                    let free = self
+                        .spatial_query
+                        .cast_shape(
+                            &shape,
+                            center,
+                            Quat::IDENTITY,
+                            direction,
+                            &ShapeCastConfig::from_max_distance(distance),
+                            &filter,
+                        )
+                        .map_or(distance, |hit| hit.distance);
+                    position += direction * free;
+                }
+
+                if let Some(hit) = self.spatial_query.cast_shape(
+                    &shape,
+                    position,
+                    Quat::IDENTITY,
+                    Dir3::NEG_Y,
+                    &ShapeCastConfig::from_max_distance(MAX_DROP_FALL),
+                    &filter,
+                ) {
+                    position.y -= hit.distance;
+                }
+                position
+            })
+            .collect()
+    }
+
+    /// Lowest position from `origin` up to [`MAX_DROP_RISE`] above it where `shape` does not
+    /// overlap terrain, or `origin` if there is none.
+    fn free_position(&self, shape: &Collider, filter: &SpatialQueryFilter, origin: Vec3) -> Vec3 {
+        let steps = (MAX_DROP_RISE / ITEM_RADIUS) as u32;
+        (0..=steps)
+            .map(|step| origin + Vec3::Y * (step as f32 * ITEM_RADIUS))
+            .find(|&position| {
+                self.spatial_query
+                    .shape_intersections(shape, position, Quat::IDENTITY, filter)
+                    .is_empty()
+            })
+            .unwrap_or(origin)
+    }
+}
+
 #[derive(Resource)]
 pub struct DroppedItemAssets {
     // TODO: mesh should also be a HashMap
@@ -166,106 +322,153 @@ fn add_item_texture(
     }
 }
 
+/// Stacks of the same item closer than this are merged.
+const MERGE_DISTANCE: f32 = 1.0;
+
+/// Merges stacks of the same item and data overlapping each other, including ones already
+/// overlapping when spawned. Quantities are moved into the largest stacks, and emptied stacks
+/// despawned.
 fn merge_items(
+    spatial_query: SpatialQuery,
+    registry: Res<ItemRegistry>,
+    mut items: Query<(Entity, &mut DroppedItem, &Transform)>,
     mut commands: Commands,
-    mut collision_started: MessageReader<CollisionStart>,
-    merge_sensors: Query<&ChildOf, With<ItemSensor>>,
-    item_stack_objs: Query<(Entity, &DroppedItem)>,
-    transforms: Query<&Transform>,
 ) -> Result<()> {
-    let mut merged = EntityHashSet::default();
-
-    for collision in collision_started.read() {
-        let &CollisionStart {
-            collider1,
-            collider2,
-            ..
-        } = collision;
-        // TODO: handle multiple merges
-        if merged.contains(&collider1) || merged.contains(&collider2) {
-            continue;
+    let stacks = items
+        .iter()
+        .map(|(entity, item, transform)| (entity, item.item_stack.clone(), transform.translation))
+        .collect::<Vec<_>>();
+    let indices = stacks
+        .iter()
+        .enumerate()
+        .map(|(i, &(entity, ..))| (entity, i))
+        .collect::<EntityHashMap<_>>();
+
+    // Hits item colliders whose centers are within `MERGE_DISTANCE`
+    let shape = Collider::sphere(MERGE_DISTANCE - ITEM_RADIUS);
+    let filter = SpatialQueryFilter::from_mask(GameLayer::Object);
+    let mut overlaps = Vec::new();
+    for (i, &(_, _, position)) in stacks.iter().enumerate() {
+        for hit in spatial_query.shape_intersections(&shape, position, Quat::IDENTITY, &filter) {
+            if let Some(&j) = indices.get(&hit)
+                && i < j
+            {
+                overlaps.push((i, j));
+            }
         }
-        let Ok([parent1, parent2]) = merge_sensors
-            .get_many([collider1, collider2])
-            .map(|cs| cs.map(|c| c.parent()))
-        else {
-            continue;
-        };
+    }
+    if overlaps.is_empty() {
+        return Ok(());
+    }
 
-        let [stack1, stack2] = item_stack_objs.get_many([parent1, parent2])?;
+    let quantities = merged_quantities(
+        &stacks
+            .iter()
+            .map(|(_, stack, _)| stack.clone())
+            .collect::<Vec<_>>(),
+        &overlaps,
+        |item_id| registry.max_stack(item_id),
+    );
+    for ((entity, stack, _), quantity) in stacks.iter().zip(quantities) {
+        let entity = *entity;
+        if quantity == 0 {
+            commands.entity(entity).despawn();
+        } else if quantity != stack.quantity() {
+            items.get_mut(entity)?.1.item_stack.set_quantity(quantity)?;
+        }
+    }
 
-        if stack1.1.item_stack.item_id != stack2.1.item_stack.item_id {
-            continue;
+    Ok(())
+}
+
+/// Quantities of `stacks` after merging the stacks that stack together and are connected by
+/// `overlaps`, given as pairs of indices. Each cluster fills as few stacks of up to
+/// `max_stack` as possible, preferring the largest ones, and emptied stacks get zero.
+fn merged_quantities(
+    stacks: &[ItemStack],
+    overlaps: &[(usize, usize)],
+    max_stack: impl Fn(ItemId) -> u32,
+) -> Vec<u32> {
+    let mut clusters = UnionFind::new(stacks.len());
+    for &(a, b) in overlaps {
+        if stacks[a].stacks_with(&stacks[b]) {
+            clusters.union(a, b);
         }
+    }
 
-        merged.insert(collider1);
-        merged.insert(collider2);
+    let mut quantities = stacks.iter().map(ItemStack::quantity).collect::<Vec<_>>();
+    for mut cluster in clusters.groups() {
+        let max_stack = max_stack(stacks[cluster[0]].item_id);
+        cluster.sort_by_key(|&i| Reverse(quantities[i]));
+        let mut remaining = cluster.iter().map(|&i| quantities[i]).sum::<u32>();
+        for i in cluster {
+            // Stacks already above the limit keep their quantity, so that nothing is lost
+            quantities[i] = remaining.min(max_stack.max(stacks[i].quantity()));
+            remaining -= quantities[i];
+        }
+    }
+    quantities
+}
 
-        let mid_translation = transforms
-            .get_many([parent1, parent2])?
-            .map(|t| t.translation)
-            .into_iter()
-            .sum::<Vec3>()
-            / 2.0;
+/// Disjoint sets of indices.
+struct UnionFind {
+    parents: Vec<usize>,
+}
 
-        let total_quantity = stack1.1.item_stack.quantity() + stack2.1.item_stack.quantity();
-        if total_quantity > ItemStack::MAX_QUANTITY {
-            commands.entity(stack1.0).insert(DroppedItem {
-                item_stack: ItemStack::new(stack1.1.item_stack.item_id, ItemStack::MAX_QUANTITY)?,
-            });
-            commands.entity(stack2.0).insert(DroppedItem {
-                item_stack: ItemStack::new(
-                    stack2.1.item_stack.item_id,
-                    total_quantity - ItemStack::MAX_QUANTITY,
-                )?,
-            });
-            continue;
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        UnionFind {
+            parents: (0..len).collect(),
         }
-        let merged_item_stack = ItemStack::new(stack1.1.item_stack.item_id, total_quantity)?;
-        commands.entity(stack1.0).despawn();
-        commands.entity(stack2.0).despawn();
+    }
 
-        commands.spawn((
-            dropped_item_bundle(merged_item_stack)?,
-            Transform::from_translation(mid_translation),
-        ));
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            // Path halving
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
     }
 
-    Ok(())
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[a] = b;
+    }
+
+    /// Indices grouped by set, in increasing order within each group.
+    fn groups(&mut self) -> Vec<Vec<usize>> {
+        let mut groups = HashMap::<usize, Vec<usize>>::new();
+        for i in 0..self.parents.len() {
+            let root = self.find(i);
+            groups.entry(root).or_default().push(i);
+        }
+        groups.into_values().collect()
+    }
 }
 
 /// A character with this component and an inventory can pick up dropped items.
 #[derive(Component, Default, Clone, Copy)]
 pub struct PickupItems;
 
+/// Picks up items touching a character, as long as its inventory has room.
+/// Items are checked every frame rather than on [`CollisionStart`], so items whose
+/// [`PickupDelay`] ends while touching a character are picked up too.
 fn pickup_items(
     chars: Query<&Children, With<PickupItems>>,
     mut inventories: Query<&mut Inventory>,
-    item_objs: Query<(&DroppedItem, &Transform)>,
-    item_sensors: Query<&ChildOf, With<ItemSensor>>,
-    mut collision_started: MessageReader<CollisionStart>,
+    mut item_objs: Query<&mut DroppedItem, Without<PickupDelay>>,
+    item_sensors: Query<(&ChildOf, &CollidingEntities), With<ItemSensor>>,
+    registry: Res<ItemRegistry>,
     mut commands: Commands,
 ) -> Result<()> {
-    for collision in collision_started.read() {
-        let &CollisionStart {
-            collider1,
-            collider2,
-            ..
-        } = collision;
-
-        let (player_children, item_id) = if let Ok(player_children) = chars.get(collider1)
-            && let Ok(item_sensor_parent) = item_sensors.get(collider2)
-        {
-            (player_children, item_sensor_parent.parent())
-        } else if let Ok(player_children) = chars.get(collider2)
-            && let Ok(item_sensor_parent) = item_sensors.get(collider1)
-        {
-            (player_children, item_sensor_parent.parent())
-        } else {
+    for (&ChildOf(item_id), colliding) in &item_sensors {
+        let Ok(mut item_obj) = item_objs.get_mut(item_id) else {
+            continue;
+        };
+        let Some(player_children) = colliding.iter().find_map(|&c| chars.get(c).ok()) else {
             continue;
         };
-
-        let (item_obj, item_transform) = item_objs.get(item_id)?;
 
         let inventory = player_children
             .iter()
@@ -273,15 +476,12 @@ fn pickup_items(
             .ok_or("Player has no inventory")?;
         let mut inventory = inventories.get_mut(inventory)?;
 
-        if let Err(remaining) = inventory.add_item_stack(item_obj.item_stack) {
-            if remaining.quantity() == item_obj.item_stack.quantity() {
-                continue;
+        if let Err(remaining) = inventory.add_item_stack(item_obj.item_stack.clone(), &registry) {
+            // Leave the rest on the ground where it is
+            if remaining.quantity() != item_obj.item_stack.quantity() {
+                item_obj.item_stack.set_quantity(remaining.quantity())?;
             }
-
-            commands.spawn((
-                dropped_item_bundle(remaining)?,
-                Transform::from_translation(item_transform.translation),
-            ));
+            continue;
         }
 
         commands.entity(item_id).despawn();
@@ -291,9 +491,143 @@ fn pickup_items(
     Ok(())
 }
 
+fn tick_pickup_delays(
+    mut delays: Query<(Entity, &mut PickupDelay)>,
+    mut commands: Commands,
+    time: Res<Time>,
+) {
+    for (entity, mut delay) in &mut delays {
+        if delay.0.tick(time.delta()).is_finished() {
+            commands.entity(entity).remove::<PickupDelay>();
+        }
+    }
+}
+
+/// Pulls items toward the nearest character within [`DroppedItemSettings::magnet_radius`]
+/// that has room for them.
+fn attract_items(
+    settings: Res<DroppedItemSettings>,
+    mut items: Query<(&DroppedItem, &Transform, &mut LinearVelocity), Without<PickupDelay>>,
+    chars: Query<(&Transform, &Children), With<PickupItems>>,
+    inventories: Query<&Inventory>,
+    registry: Res<ItemRegistry>,
+    time: Res<Time>,
+) {
+    for (item, transform, mut velocity) in &mut items {
+        let position = transform.translation;
+        let target = chars
+            .iter()
+            .filter(|(char_transform, _)| {
+                char_transform.translation.distance(position) <= settings.magnet_radius
+            })
+            .filter(|(_, children)| {
+                children
+                    .iter()
+                    .find_map(|c| inventories.get(c).ok())
+                    .is_some_and(|inventory| inventory.room_for(&item.item_stack, &registry) > 0)
+            })
+            .map(|(char_transform, _)| char_transform.translation)
+            .min_by(|a, b| {
+                a.distance_squared(position)
+                    .total_cmp(&b.distance_squared(position))
+            });
+        let Some(target) = target else {
+            continue;
+        };
+
+        let pull = (target - position).normalize_or_zero() * settings.magnet_speed;
+        velocity.0 = velocity
+            .0
+            .lerp(pull, (MAGNET_RESPONSIVENESS * time.delta_secs()).min(1.0));
+    }
+}
+
+/// How quickly attracted items reach [`DroppedItemSettings::magnet_speed`].
+const MAGNET_RESPONSIVENESS: f32 = 8.0;
+/// Times per second items blink before despawning.
+const BLINK_FREQUENCY: f32 = 4.0;
+
+/// Despawns items after [`DroppedItemSettings::lifetime`], blinking them before that.
+fn age_dropped_items(
+    settings: Res<DroppedItemSettings>,
+    mut items: Query<(Entity, &mut ItemAge, &mut Visibility)>,
+    mut commands: Commands,
+    time: Res<Time>,
+) {
+    for (entity, mut age, mut visibility) in &mut items {
+        age.0 += time.delta();
+        let Some(remaining) = settings.lifetime.checked_sub(age.0) else {
+            // May have been merged or picked up this frame
+            commands.entity(entity).try_despawn();
+            continue;
+        };
+
+        let hidden = remaining < settings.blink_duration
+            && (remaining.as_secs_f32() * BLINK_FREQUENCY * 2.0) as u32 % 2 == 1;
+        visibility.set_if_neq(if hidden {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        });
+    }
+}
+
+/// Spawns the meshes of new items, and rebuilds them when the quantity changes.
+fn update_item_visuals(
+    items: Query<(Entity, &DroppedItem, Option<&Children>), Changed<DroppedItem>>,
+    visuals: Query<(), With<ItemVisual>>,
+    assets: Res<DroppedItemAssets>,
+    mut commands: Commands,
+) {
+    for (entity, item, children) in &items {
+        if let Some(children) = children {
+            for child in children.iter() {
+                if visuals.contains(child) {
+                    commands.entity(child).despawn();
+                }
+            }
+        }
+
+        let item_stack = &item.item_stack;
+        commands
+            .entity(entity)
+            .insert(Name::new(format!("DroppedItem ({:?})", item_stack)));
+        let material = assets
+            .material_map
+            .get(&item_stack.item_id)
+            .cloned()
+            .unwrap_or_default();
+
+        if !item_stack.item_id.is_block() {
+            commands.spawn((
+                ItemVisual,
+                Mesh3d(assets.item_mesh.clone()),
+                MeshMaterial3d(material),
+                ChildOf(entity),
+            ));
+            continue;
+        }
+        let num_cubes = (item_stack.quantity() as f32).log2().ceil() as u32 + 1;
+        for i in 0..num_cubes {
+            let offset = Vec3::new(
+                (rand::random::<f32>() - 0.5) * 0.2,
+                (rand::random::<f32>() - 0.5) * 0.2,
+                (rand::random::<f32>() - 0.5) * 0.2,
+            );
+            commands.spawn((
+                ItemVisual,
+                Transform::from_translation(offset + Vec3::Y * (i as f32 * 0.05)),
+                Mesh3d(assets.block_mesh.clone()),
+                MeshMaterial3d(material.clone()),
+                ChildOf(entity),
+            ));
+        }
+    }
+}
+
 fn animate_dropped_items(
     mut items: Query<&Children, With<DroppedItem>>,
-    mut mesh_tr: Query<&mut Transform, With<Mesh3d>>,
+    mut mesh_tr: Query<&mut Transform, With<ItemVisual>>,
     time: Res<Time>,
 ) {
     for children in &mut items {
@@ -304,3 +638,133 @@ fn animate_dropped_items(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::item::ItemData;
+
+    fn stack(id: u32, quantity: u32) -> ItemStack {
+        ItemStack::new(ItemId(id), quantity).unwrap()
+    }
+
+    #[test]
+    fn merging_fills_largest_stacks() {
+        let stacks = [stack(1, 20), stack(1, 40), stack(2, 10), stack(1, 30)];
+        let overlaps = [(0, 1), (1, 2), (2, 3), (1, 3)];
+        assert_eq!(
+            merged_quantities(&stacks, &overlaps, |_| ItemStack::MAX_QUANTITY),
+            [0, 64, 10, 26]
+        );
+    }
+
+    #[test]
+    fn merging_respects_max_stack_and_data() {
+        let named = stack(1, 5).with_data(ItemData {
+            custom_name: Some("Lucky".into()),
+            ..default()
+        });
+        let stacks = [stack(1, 10), stack(1, 12), named];
+        let overlaps = [(0, 1), (1, 2)];
+        assert_eq!(merged_quantities(&stacks, &overlaps, |_| 16), [6, 16, 5]);
+    }
+
+    /// Stacks of 3 items, where item `i` stacks up to `MAX_STACKS[i]`.
+    const MAX_STACKS: [u32; 3] = [ItemStack::MAX_QUANTITY, 16, 1];
+
+    fn stacks() -> impl Strategy<Value = Vec<ItemStack>> {
+        prop::collection::vec(
+            (0..3usize).prop_flat_map(|id| (Just(id), 1..=MAX_STACKS[id])),
+            1..24,
+        )
+        .prop_map(|stacks| {
+            stacks
+                .into_iter()
+                .map(|(id, quantity)| stack(id as u32, quantity))
+                .collect()
+        })
+    }
+
+    fn total<'a>(stacks: impl IntoIterator<Item = (&'a ItemStack, u32)>, item: ItemId) -> u32 {
+        stacks
+            .into_iter()
+            .filter(|(stack, _)| stack.item_id == item)
+            .map(|(_, quantity)| quantity)
+            .sum()
+    }
+
+    proptest! {
+        #[test]
+        fn merging_conserves_quantities(
+            stacks in stacks(),
+            overlaps in prop::collection::vec((0..24usize, 0..24usize), 0..48),
+        ) {
+            let overlaps = overlaps
+                .into_iter()
+                .filter(|&(a, b)| a < stacks.len() && b < stacks.len())
+                .collect::<Vec<_>>();
+            let quantities =
+                merged_quantities(&stacks, &overlaps, |item_id| MAX_STACKS[item_id.0 as usize]);
+
+            for (stack, &q) in stacks.iter().zip(&quantities) {
+                prop_assert!(q <= MAX_STACKS[stack.item_id.0 as usize]);
+            }
+            let merged = stacks.iter().zip(quantities.iter().copied());
+            let before = stacks.iter().map(|stack| (stack, stack.quantity()));
+            for id in 0..3 {
+                prop_assert_eq!(
+                    total(merged.clone(), ItemId(id)),
+                    total(before.clone(), ItemId(id))
+                );
+            }
+            // Stacks not overlapping anything are left alone
+            for (i, (stack, &after)) in stacks.iter().zip(&quantities).enumerate() {
+                if !overlaps.iter().any(|&(a, b)| a != b && (a == i || b == i)) {
+                    prop_assert_eq!(stack.quantity(), after);
+                }
+            }
+        }
+
+        #[test]
+        fn union_find_groups_connected_indices(
+            len in 1..24usize,
+            edges in prop::collection::vec((0..24usize, 0..24usize), 0..48),
+        ) {
+            let edges = edges
+                .into_iter()
+                .filter(|&(a, b)| a < len && b < len)
+                .collect::<Vec<_>>();
+            let mut sets = UnionFind::new(len);
+            for &(a, b) in &edges {
+                sets.union(a, b);
+            }
+            let groups = sets.groups();
+
+            let mut indices = groups.concat();
+            indices.sort();
+            prop_assert_eq!(indices, (0..len).collect::<Vec<_>>());
+
+            // Label each index with the smallest index connected to it
+            let mut labels = (0..len).collect::<Vec<_>>();
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for &(a, b) in &edges {
+                    let label = labels[a].min(labels[b]);
+                    if labels[a] != label || labels[b] != label {
+                        labels[a] = label;
+                        labels[b] = label;
+                        changed = true;
+                    }
+                }
+            }
+            for group in &groups {
+                prop_assert!(group.iter().all(|&i| labels[i] == labels[group[0]]));
+            }
+            let components = labels.iter().enumerate().filter(|&(i, &l)| i == l).count();
+            prop_assert_eq!(groups.len(), components);
+        }
+    }
+}
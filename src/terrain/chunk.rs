@@ -19,6 +19,7 @@ impl Plugin for ChunkPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ChunkMap>()
             .add_message::<ChunkUpdated>()
+            .add_message::<BlockRemoved>()
             .add_observer(update_chunk_map)
             .add_observer(remove_chunk_map);
 
@@ -45,7 +46,6 @@ impl BlockId {
     }
 
     /// Blocks rendered as liquids
-    #[expect(dead_code)]
     pub const fn is_liquid(self) -> bool {
         self.0 > 32 && self.0 <= 64
     }
@@ -187,9 +187,15 @@ impl<'w, 's> ReadBlocks<'w, 's> {
         None
     }
 
-    fn get_block(&self, position: IVec3) -> Result<(BlockId, Entity)> {
+    pub fn get_block(&self, position: IVec3) -> Result<(BlockId, Entity)> {
         get_block_common(&self.chunks, &self.chunk_map, position)
     }
+
+    /// Returns the chunk at chunk position `position`, if it is loaded.
+    pub fn get_chunk(&self, position: IVec2) -> Option<&Chunk> {
+        let &entity = self.chunk_map.0.get(&position)?;
+        self.chunks.get(entity).ok()
+    }
 }
 
 fn get_block_common(
@@ -225,6 +231,7 @@ pub struct WriteBlocks<'w, 's> {
     chunks: Query<'w, 's, Write<Chunk>>,
     chunk_map: Res<'w, ChunkMap>,
     writer: MessageWriter<'w, ChunkUpdated>,
+    removed_writer: MessageWriter<'w, BlockRemoved>,
 }
 
 impl<'w, 's> WriteBlocks<'w, 's> {
@@ -246,7 +253,13 @@ impl<'w, 's> WriteBlocks<'w, 's> {
             .ok_or(BevyError::from("Chunk not found"))?;
 
         let mut chunk = self.chunks.get_mut(chunk_id)?;
-        chunk.set_block(IVec3::new(local_x, local_y, local_z), block);
+        let local = IVec3::new(local_x, local_y, local_z);
+        let old_block = chunk.get_block(local);
+        chunk.set_block(local, block);
+
+        if block == BlockId::AIR && old_block != BlockId::AIR {
+            self.removed_writer.write(BlockRemoved(position));
+        }
 
         self.trigger_update(chunk_x, chunk_z, IVec3::new(local_x, local_y, local_z));
 
@@ -353,6 +366,10 @@ fn get_chunk_and_local_coords(position: IVec3) -> (i32, i32, i32, i32, i32) {
 #[derive(Message)]
 pub struct ChunkUpdated(pub Entity);
 
+/// Sent when a block is replaced with air through [`WriteBlocks`]. Contains the world block position.
+#[derive(Message, Debug, Clone, Copy)]
+pub struct BlockRemoved(pub IVec3);
+
 #[derive(EntityEvent)]
 pub struct ChunkUnloaded(Entity);
 
@@ -2,8 +2,16 @@ use avian3d::prelude::LinearVelocity;
 use bevy::{prelude::*, window::PrimaryWindow};
 
 use crate::{
-    item::ItemStack, object::dropped_item::dropped_item_bundle, pause::Pause,
+    inventory::Inventory,
+    item::{
+        ItemRegistry,
+        loot::{BlockLoot, LootContext},
+        tool::BlockMining,
+    },
+    object::dropped_item::DropPlacer,
+    pause::Pause,
     terrain::chunk::BlockId,
+    ui::hotbar::Hotbar,
 };
 
 use super::chunk::{HoveredBlock, WriteBlocks};
@@ -30,6 +38,11 @@ fn on_click(
     mut blocks: WriteBlocks,
     mut commands: Commands,
     pause: Res<State<Pause>>,
+    mut loot: BlockLoot,
+    mut placer: DropPlacer,
+    hotbars: Query<&Hotbar>,
+    mut inventories: Query<&mut Inventory>,
+    registry: Res<ItemRegistry>,
 ) -> Result<()> {
     if pause.0 {
         return Ok(());
@@ -45,19 +58,49 @@ fn on_click(
                 return Ok(());
             }
 
-            blocks.set_block(block_pos.0, BlockId(0))?;
-            let random_vel = LinearVelocity(Vec3::new(
-                (rand::random::<f32>() - 0.5) * 2.0,
-                rand::random::<f32>() * 2.0,
-                (rand::random::<f32>() - 0.5) * 2.0,
-            ));
-            commands.spawn((
-                dropped_item_bundle(ItemStack::new(block_id.as_item_id(), 1)?)?,
-                (
-                    Transform::from_translation(block_pos.0.as_vec3() + Vec3::splat(0.5)),
-                    random_vel,
-                ),
-            ));
+            // Item in the active hotbar slot
+            let held = hotbars.iter().find_map(|hotbar| {
+                let slot = hotbar.active_slot as usize;
+                let inventory = inventories.get(hotbar.inventory).ok()?;
+                let stack = inventory.slots.get(slot)?.as_ref()?;
+                Some((hotbar.inventory, slot, stack.item_id))
+            });
+            let tool_id = held.map(|(.., item_id)| item_id);
+            let tool = tool_id.and_then(|item_id| registry.tool(item_id));
+            let mining = BlockMining::of(block_id);
+            // Tools only wear out on blocks they are made for
+            if let (Some(tool), Some((inventory, slot, _))) = (tool, held)
+                && mining.tool == Some(tool.kind)
+            {
+                let mut inventory = inventories.get_mut(inventory)?;
+                if let Some(stack) = &mut inventory.slots[slot]
+                    && tool.wear(stack)
+                {
+                    debug!("Tool in slot {slot} broke");
+                    inventory.slots[slot] = None;
+                }
+            }
+
+            if blocks
+                .damage_block(block_pos.0, mining.damage(tool))?
+                .is_none()
+                || !mining.harvestable(tool)
+            {
+                return Ok(());
+            }
+            let context = LootContext {
+                cause: None,
+                tool: tool_id,
+            };
+            let center = block_pos.0.as_vec3() + Vec3::splat(0.5);
+            for item in placer.drop_items(center, loot.roll(block_id, &context))? {
+                let random_vel = LinearVelocity(Vec3::new(
+                    (rand::random::<f32>() - 0.5) * 2.0,
+                    rand::random::<f32>() * 2.0,
+                    (rand::random::<f32>() - 0.5) * 2.0,
+                ));
+                commands.entity(item).insert(random_vel);
+            }
         }
         PointerButton::Secondary => {
             debug!("Hit pos: {:?}, Hit face: {:?}", block_pos.0, block_pos.1);
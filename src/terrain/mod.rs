@@ -1,3 +1,5 @@
 pub mod chunk;
 pub mod edit;
+pub mod pathfinding;
 pub mod render;
+pub mod support;
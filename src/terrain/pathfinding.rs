@@ -0,0 +1,406 @@
+//! A* pathfinding over the block grid.
+//!
+//! Paths are made of cells a character can stand in: a passable block on top of a terrain or
+//! solid block, with enough passable blocks above it for the character to fit.
+
+use std::{cmp::Reverse, collections::BinaryHeap, sync::Arc};
+
+use bevy::{
+    math::FloatOrd,
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+
+use super::chunk::{BlockId, CHUNK_HEIGHT, CHUNK_SIZE, Chunk};
+
+/// Searches give up after visiting this many cells.
+const MAX_VISITED: usize = 8192;
+
+/// Movement capabilities of a character.
+#[derive(Clone, Copy, Debug)]
+pub struct NavAgent {
+    /// Number of blocks the character occupies vertically.
+    pub height: i32,
+    /// Whether the character can walk up terrain slopes of one block per block.
+    pub walkable_slope: bool,
+    /// Number of blocks the character can climb by jumping.
+    pub jump_height: i32,
+    /// Number of blocks the character is willing to drop down.
+    pub max_drop: i32,
+    /// Number of blocks wide a gap can be for the character to jump over it.
+    pub max_gap: i32,
+}
+
+impl Default for NavAgent {
+    fn default() -> Self {
+        NavAgent {
+            height: 2,
+            walkable_slope: true,
+            jump_height: 1,
+            max_drop: 3,
+            max_gap: 1,
+        }
+    }
+}
+
+/// How a character gets into the cell of a [`PathStep`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StepKind {
+    Walk,
+    Jump,
+    Fall,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PathStep {
+    /// Cell the character stands in after this step.
+    pub cell: IVec3,
+    pub kind: StepKind,
+}
+
+impl PathStep {
+    /// Position of the bottom center of the cell.
+    pub fn position(&self) -> Vec3 {
+        self.cell.as_vec3() + Vec3::new(0.5, 0.0, 0.5)
+    }
+}
+
+type ChunkBlocks = Arc<[[[BlockId; CHUNK_SIZE]; CHUNK_HEIGHT]; CHUNK_SIZE]>;
+
+/// Copy of the blocks of some chunks, so that paths can be searched off the main thread.
+#[derive(Clone, Default)]
+pub struct BlockSnapshot(HashMap<IVec2, ChunkBlocks>);
+
+impl BlockSnapshot {
+    pub fn insert(&mut self, chunk: &Chunk) {
+        self.0.insert(chunk.position, chunk.blocks.clone());
+    }
+
+    /// Returns `None` for positions in chunks missing from the snapshot, and below the world.
+    pub fn get_block(&self, position: IVec3) -> Option<BlockId> {
+        if position.y < 0 {
+            return None;
+        }
+        if position.y >= CHUNK_HEIGHT as i32 {
+            return Some(BlockId::AIR);
+        }
+        let chunk = IVec2::new(
+            position.x.div_euclid(CHUNK_SIZE as i32),
+            position.z.div_euclid(CHUNK_SIZE as i32),
+        );
+        let blocks = self.0.get(&chunk)?;
+        Some(
+            blocks[position.x.rem_euclid(CHUNK_SIZE as i32) as usize][position.y as usize]
+                [position.z.rem_euclid(CHUNK_SIZE as i32) as usize],
+        )
+    }
+
+    /// Positions of the chunks in the snapshot.
+    pub fn chunks(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.0.keys().copied()
+    }
+}
+
+/// Returns the chunk position containing the block `position`.
+pub fn chunk_of(position: IVec3) -> IVec2 {
+    IVec2::new(
+        position.x.div_euclid(CHUNK_SIZE as i32),
+        position.z.div_euclid(CHUNK_SIZE as i32),
+    )
+}
+
+fn is_passable(block: Option<BlockId>) -> bool {
+    block.is_some_and(|b| !b.is_terrain() && !b.is_solid())
+}
+
+fn is_floor(block: Option<BlockId>) -> bool {
+    block.is_some_and(|b| b.is_terrain() || b.is_solid())
+}
+
+struct Grid<'a, F> {
+    get_block: F,
+    agent: &'a NavAgent,
+}
+
+impl<F: Fn(IVec3) -> Option<BlockId>> Grid<'_, F> {
+    /// Whether the character fits in `cell`, ignoring what is below it.
+    fn fits(&self, cell: IVec3) -> bool {
+        (0..self.agent.height).all(|dy| is_passable((self.get_block)(cell + IVec3::Y * dy)))
+    }
+
+    fn can_stand(&self, cell: IVec3) -> bool {
+        is_floor((self.get_block)(cell - IVec3::Y)) && self.fits(cell)
+    }
+
+    fn neighbors(&self, cell: IVec3, out: &mut Vec<(PathStep, f32)>) {
+        const ORTHOGONAL: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+        const DIAGONAL: [IVec3; 4] = [
+            IVec3::new(1, 0, 1),
+            IVec3::new(1, 0, -1),
+            IVec3::new(-1, 0, 1),
+            IVec3::new(-1, 0, -1),
+        ];
+
+        let step = |cell, kind| PathStep { cell, kind };
+
+        for dir in ORTHOGONAL {
+            let next = cell + dir;
+            if self.can_stand(next) {
+                out.push((step(next, StepKind::Walk), 1.0));
+                continue;
+            }
+
+            if !self.fits(next) {
+                // Climb up, walking on terrain slopes and jumping otherwise
+                let max_climb = self.agent.jump_height.max(self.agent.walkable_slope as i32);
+                for dy in 1..=max_climb {
+                    // Room to jump up from the current cell
+                    if !is_passable((self.get_block)(
+                        cell + IVec3::Y * (self.agent.height + dy - 1),
+                    )) {
+                        break;
+                    }
+                    let up = next + IVec3::Y * dy;
+                    if self.can_stand(up) {
+                        let slope = dy == 1
+                            && self.agent.walkable_slope
+                            && (self.get_block)(next).is_some_and(|b| b.is_terrain());
+                        if slope {
+                            out.push((step(up, StepKind::Walk), 2.0));
+                        } else if dy <= self.agent.jump_height {
+                            out.push((step(up, StepKind::Jump), 1.0 + dy as f32));
+                        }
+                        break;
+                    }
+                }
+                continue;
+            }
+
+            // Nothing to stand on: drop down, or jump over the gap
+            for dy in 1..=self.agent.max_drop {
+                let down = next - IVec3::Y * dy;
+                if !is_passable((self.get_block)(down)) {
+                    break;
+                }
+                if self.can_stand(down) {
+                    out.push((step(down, StepKind::Fall), 1.0 + dy as f32));
+                    break;
+                }
+            }
+            if self.agent.jump_height > 0 {
+                for gap in 2..=self.agent.max_gap + 1 {
+                    let far = cell + dir * gap;
+                    if self.can_stand(far) {
+                        out.push((step(far, StepKind::Jump), 1.0 + gap as f32));
+                        break;
+                    }
+                    if !self.fits(far) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        for dir in DIAGONAL {
+            let next = cell + dir;
+            // Don't cut corners
+            if self.can_stand(next)
+                && self.fits(cell + IVec3::X * dir.x)
+                && self.fits(cell + IVec3::Z * dir.z)
+            {
+                out.push((step(next, StepKind::Walk), std::f32::consts::SQRT_2));
+            }
+        }
+    }
+}
+
+/// Returns the cell a character at `position` stands in, searching down at most `max_depth`
+/// blocks.
+pub fn ground_cell(
+    get_block: impl Fn(IVec3) -> Option<BlockId>,
+    position: Vec3,
+    max_depth: i32,
+) -> Option<IVec3> {
+    let start = position.floor().as_ivec3();
+    (0..=max_depth)
+        .map(|dy| start - IVec3::Y * dy)
+        .find(|&cell| is_passable(get_block(cell)) && is_floor(get_block(cell - IVec3::Y)))
+}
+
+/// Finds the cheapest path from `start` to `goal`, not including `start`.
+///
+/// `get_block` returns `None` for unknown positions, which are never entered.
+/// Returns `None` if `goal` can't be reached.
+pub fn find_path(
+    get_block: impl Fn(IVec3) -> Option<BlockId>,
+    start: IVec3,
+    goal: IVec3,
+    agent: &NavAgent,
+) -> Option<Vec<PathStep>> {
+    let grid = Grid { get_block, agent };
+    if !grid.can_stand(goal) {
+        return None;
+    }
+
+    let heuristic = |cell: IVec3| cell.as_vec3().distance(goal.as_vec3());
+
+    let mut open = BinaryHeap::new();
+    let mut costs = HashMap::new();
+    let mut came_from: HashMap<IVec3, PathStep> = HashMap::new();
+    let mut closed = HashSet::new();
+    let mut neighbors = vec![];
+
+    costs.insert(start, 0.0);
+    open.push(Reverse((FloatOrd(heuristic(start)), start.to_array())));
+
+    while let Some(Reverse((_, cell))) = open.pop() {
+        let cell = IVec3::from_array(cell);
+        if cell == goal {
+            let mut path = vec![];
+            let mut current = goal;
+            while let Some(&step) = came_from.get(&current) {
+                path.push(PathStep {
+                    cell: current,
+                    kind: step.kind,
+                });
+                current = step.cell;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if !closed.insert(cell) {
+            continue;
+        }
+        if closed.len() > MAX_VISITED {
+            debug!("Gave up searching path from {start} to {goal}");
+            return None;
+        }
+
+        let cost = costs[&cell];
+        neighbors.clear();
+        grid.neighbors(cell, &mut neighbors);
+        for &(step, step_cost) in &neighbors {
+            let next_cost = cost + step_cost;
+            if costs.get(&step.cell).is_some_and(|&c| c <= next_cost) {
+                continue;
+            }
+            costs.insert(step.cell, next_cost);
+            // Store where the step came from, along with how it is made
+            came_from.insert(
+                step.cell,
+                PathStep {
+                    cell,
+                    kind: step.kind,
+                },
+            );
+            open.push(Reverse((
+                FloatOrd(next_cost + heuristic(step.cell)),
+                step.cell.to_array(),
+            )));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flat ground at `y = 0`, with extra blocks on top of it.
+    fn world(blocks: &[(IVec3, BlockId)]) -> impl Fn(IVec3) -> Option<BlockId> + use<> {
+        let blocks = blocks.iter().copied().collect::<HashMap<_, _>>();
+        move |pos| {
+            if pos.x.abs() > 16 || pos.z.abs() > 16 || pos.y < 0 {
+                None
+            } else if let Some(&block) = blocks.get(&pos) {
+                Some(block)
+            } else if pos.y == 0 {
+                Some(BlockId(2))
+            } else {
+                Some(BlockId::AIR)
+            }
+        }
+    }
+
+    const STONE: BlockId = BlockId(2);
+    const CUBE: BlockId = BlockId(65);
+
+    #[test]
+    fn path_goes_around_walls() {
+        let wall = (-3..=3)
+            .flat_map(|z| [(IVec3::new(2, 1, z), CUBE), (IVec3::new(2, 2, z), CUBE)])
+            .collect::<Vec<_>>();
+        let agent = NavAgent {
+            jump_height: 0,
+            ..default()
+        };
+
+        let path = find_path(
+            world(&wall),
+            IVec3::new(0, 1, 0),
+            IVec3::new(4, 1, 0),
+            &agent,
+        )
+        .unwrap();
+
+        assert_eq!(path.last().unwrap().cell, IVec3::new(4, 1, 0));
+        assert!(path.iter().all(|step| step.kind == StepKind::Walk));
+        assert!(
+            path.iter()
+                .all(|step| step.cell.x != 2 || step.cell.z.abs() > 3)
+        );
+    }
+
+    #[test]
+    fn path_climbs_blocks_by_jumping_and_slopes_by_walking() {
+        let agent = NavAgent::default();
+
+        let cube = world(&[(IVec3::new(1, 1, 0), CUBE)]);
+        let path = find_path(cube, IVec3::new(0, 1, 0), IVec3::new(1, 2, 0), &agent).unwrap();
+        assert_eq!(
+            path,
+            [PathStep {
+                cell: IVec3::new(1, 2, 0),
+                kind: StepKind::Jump
+            }]
+        );
+
+        let slope = world(&[(IVec3::new(1, 1, 0), STONE)]);
+        let path = find_path(slope, IVec3::new(0, 1, 0), IVec3::new(1, 2, 0), &agent).unwrap();
+        assert_eq!(path[0].kind, StepKind::Walk);
+
+        let agent = NavAgent {
+            walkable_slope: false,
+            jump_height: 0,
+            ..default()
+        };
+        let slope = world(&[(IVec3::new(1, 1, 0), STONE)]);
+        assert!(find_path(slope, IVec3::new(0, 1, 0), IVec3::new(1, 2, 0), &agent).is_none());
+    }
+
+    #[test]
+    fn path_jumps_over_gaps() {
+        let gap = (-16..=16)
+            .map(|z| (IVec3::new(1, 0, z), BlockId::AIR))
+            .collect::<Vec<_>>();
+
+        let path = find_path(
+            world(&gap),
+            IVec3::new(0, 1, 0),
+            IVec3::new(2, 1, 0),
+            &NavAgent {
+                max_drop: 0,
+                ..default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            path,
+            [PathStep {
+                cell: IVec3::new(2, 1, 0),
+                kind: StepKind::Jump
+            }]
+        );
+    }
+}
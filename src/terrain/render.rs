@@ -19,7 +19,9 @@ use crate::{
     terrain::chunk::BlockId,
 };
 
-use super::chunk::{CHUNK_HEIGHT, CHUNK_SIZE, Chunk, ChunkMap, ChunkUnloaded, ChunkUpdated};
+use super::chunk::{
+    BlockRemoved, CHUNK_HEIGHT, CHUNK_SIZE, Chunk, ChunkMap, ChunkUnloaded, ChunkUpdated,
+};
 
 pub struct RenderPlugin;
 
@@ -27,17 +29,25 @@ impl Plugin for RenderPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<RenderPluginSettings>()
             .init_resource::<RenderChunkMap>()
-            .add_message::<RenderChunkSpawned>()
+            .add_message::<RenderChunkUpdated>()
             .add_plugins(MaterialPlugin::<ExtendedArrayTextureMaterial>::default())
             .add_systems(Startup, setup_terrain_texture)
             .add_systems(Update, create_array_texture)
             .add_systems(Update, generate_terrain_mesh)
             .add_systems(
                 Update,
-                (spawn_generated_terrain_mesh, update_solid)
+                (
+                    track_removed_blocks,
+                    spawn_generated_terrain_mesh,
+                    update_solid,
+                )
                     .chain()
                     .after(generate_terrain_mesh),
             )
+            .add_systems(
+                Update,
+                remove_debug_gizmos.run_if(resource_changed::<RenderPluginSettings>),
+            )
             .add_observer(chunk_unloaded);
     }
 }
@@ -54,6 +64,13 @@ struct RenderChunkMap(EntityHashMap<RenderChunk>);
 struct RenderChunk {
     pub position: IVec2,
     pub id: Entity,
+    /// Terrain mesh entity. It also holds the trimesh collider of the chunk,
+    /// which is swapped in place on remesh.
+    pub mesh: Entity,
+    /// Solid block entities keyed by their local block position.
+    pub solids: HashMap<IVec3, Entity>,
+    /// World positions of blocks removed near the chunk since its last remesh.
+    pub removed: Vec<IVec3>,
 }
 
 const TERRAIN_SHADER_PATH: &str = "shaders/terrain_texture.wgsl";
@@ -158,6 +175,8 @@ struct PendingChunk(Task<PendingChunkResult>);
 
 struct PendingChunkResult {
     mesh: Mesh,
+    /// `None` if the chunk has no terrain surface.
+    collider: Option<Collider>,
     chunk_id: Entity,
     gizmo: GizmoAsset,
 }
@@ -401,8 +420,17 @@ fn generate_terrain_mesh(
 
             bv_span.exit();
 
+            let collider_span = debug_span!("Collider Generation").entered();
+            let collider = if bvmesh.indices().is_some_and(|indices| !indices.is_empty()) {
+                Collider::trimesh_from_mesh(&bvmesh)
+            } else {
+                None
+            };
+            collider_span.exit();
+
             PendingChunkResult {
                 mesh: bvmesh,
+                collider,
                 chunk_id,
                 gizmo,
             }
@@ -455,9 +483,9 @@ fn deduplicate_vertices(mesh: &mut Mesh) {
     mesh.insert_indices(Indices::U32(new_indices));
 }
 
-/// Message sent when a render chunk is (re)spawned.
+/// Message sent when a render chunk is spawned or its mesh is replaced.
 #[derive(Message)]
-struct RenderChunkSpawned {
+struct RenderChunkUpdated {
     render_chunk: Entity,
     chunk: Entity,
 }
@@ -466,6 +494,8 @@ fn spawn_generated_terrain_mesh(
     mut commands: Commands,
     mut pending: Query<(Entity, &mut PendingChunk)>,
     chunks: Query<&Chunk>,
+    colliding: Query<&CollidingEntities>,
+    aabbs: Query<&ColliderAabb>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut gizmo_assets: ResMut<Assets<GizmoAsset>>,
     mut rendered: ResMut<RenderChunkMap>,
@@ -480,77 +510,155 @@ fn spawn_generated_terrain_mesh(
 
         let PendingChunkResult {
             mesh,
+            collider,
             chunk_id,
             gizmo,
         } = result;
 
-        let bvmesh = meshes.add(mesh);
-
         let Ok(chunk) = chunks.get(chunk_id) else {
             continue;
         };
 
-        let render_chunk = commands
-            .spawn((
-                Name::new(format!(
-                    "Render Chunk ({}, {})",
-                    chunk.position.x, chunk.position.y
-                )),
-                Transform::from_xyz(
-                    chunk.position.x as f32 * CHUNK_SIZE as f32,
-                    0.0,
-                    chunk.position.y as f32 * CHUNK_SIZE as f32,
-                ),
-                Visibility::Visible,
-            ))
-            .id();
-
-        if let Some(rc) = rendered.0.get_mut(&chunk_id) {
-            commands.entity(rc.id).despawn();
-            rc.id = render_chunk;
-        } else {
-            rendered.0.insert(
-                chunk_id,
-                RenderChunk {
-                    position: chunk.position,
-                    id: render_chunk,
-                },
-            );
-        }
+        let bvmesh = meshes.add(mesh);
 
-        commands.entity(render_chunk).with_children(|parent| {
-            let mut mesh_entity = parent.spawn((
+        let render_chunk = if let Some(rc) = rendered.0.get_mut(&chunk_id) {
+            // Swap mesh and collider shape in place so that contacts with the terrain survive.
+            let mut mesh_entity = commands.entity(rc.mesh);
+            mesh_entity.insert(Mesh3d(bvmesh));
+            if let Some(collider) = collider {
+                mesh_entity.insert(collider);
+            } else {
+                mesh_entity.remove::<Collider>();
+            }
+            if settings.debug {
+                mesh_entity.insert(Gizmo {
+                    handle: gizmo_assets.add(gizmo),
+                    ..default()
+                });
+            } else {
+                mesh_entity.remove::<Gizmo>();
+            }
+
+            // Bodies sleeping on the old shape would keep floating where terrain was removed.
+            // The surface around a block is built from its neighbors, so it changes within
+            // one block of it.
+            let removed = std::mem::take(&mut rc.removed)
+                .into_iter()
+                .map(|pos| ColliderAabb::from_min_max(pos.as_vec3() - 1.0, pos.as_vec3() + 2.0))
+                .collect::<Vec<_>>();
+            if let Ok(cols) = colliding.get(rc.mesh) {
+                for &col in cols.iter() {
+                    if let Ok(aabb) = aabbs.get(col)
+                        && removed.iter().any(|region| region.intersects(aabb))
+                    {
+                        commands.queue(WakeBody(col));
+                    }
+                }
+            }
+
+            rc.id
+        } else {
+            let render_chunk = commands
+                .spawn((
+                    Name::new(format!(
+                        "Render Chunk ({}, {})",
+                        chunk.position.x, chunk.position.y
+                    )),
+                    Transform::from_xyz(
+                        chunk.position.x as f32 * CHUNK_SIZE as f32,
+                        0.0,
+                        chunk.position.y as f32 * CHUNK_SIZE as f32,
+                    ),
+                    Visibility::Visible,
+                ))
+                .id();
+
+            let mut mesh_entity = commands.spawn((
                 Mesh3d(bvmesh),
                 MeshMaterial3d(terrain_texture.material_handle.clone()),
                 RigidBody::Static,
-                ColliderConstructor::TrimeshFromMesh,
                 CollisionLayers::new([GameLayer::Terrain], GameLayer::all_bits()),
                 Transform::from_translation(Vec3::splat(-0.5)),
                 Name::new(format!(
                     "Render Chunk Mesh ({}, {})",
                     chunk.position.x, chunk.position.y
                 )),
-                // terrains are despawned and recreated, so colliding entities keep sleeping otherwise
+                // wake colliding entities when the chunk is unloaded
                 WakeCollidingEntitiesOnDespawn,
+                ChildOf(render_chunk),
             ));
+            if let Some(collider) = collider {
+                mesh_entity.insert(collider);
+            }
             if settings.debug {
-                mesh_entity.with_child(Gizmo {
+                mesh_entity.insert(Gizmo {
                     handle: gizmo_assets.add(gizmo),
                     ..default()
                 });
             }
-        });
+            let mesh = mesh_entity.id();
+
+            rendered.0.insert(
+                chunk_id,
+                RenderChunk {
+                    position: chunk.position,
+                    id: render_chunk,
+                    mesh,
+                    solids: HashMap::new(),
+                    removed: Vec::new(),
+                },
+            );
 
-        commands.write_message(RenderChunkSpawned {
+            render_chunk
+        };
+
+        commands.write_message(RenderChunkUpdated {
             render_chunk,
             chunk: chunk_id,
         });
     }
 }
 
+/// Records removed blocks on the render chunks whose mesh they are part of.
+fn track_removed_blocks(
+    mut reader: MessageReader<BlockRemoved>,
+    chunk_map: Res<ChunkMap>,
+    mut rendered: ResMut<RenderChunkMap>,
+) {
+    for &BlockRemoved(position) in reader.read() {
+        let min = (position.xz() - 1).div_euclid(IVec2::splat(CHUNK_SIZE as i32));
+        let max = (position.xz() + 1).div_euclid(IVec2::splat(CHUNK_SIZE as i32));
+        for z in min.y..=max.y {
+            for x in min.x..=max.x {
+                if let Some(chunk_id) = chunk_map.0.get(&IVec2::new(x, z))
+                    && let Some(rc) = rendered.0.get_mut(chunk_id)
+                {
+                    rc.removed.push(position);
+                }
+            }
+        }
+    }
+}
+
+/// Removes debug gizmos from chunk meshes when they are disabled.
+fn remove_debug_gizmos(
+    settings: Res<RenderPluginSettings>,
+    rendered: Res<RenderChunkMap>,
+    mut commands: Commands,
+) {
+    if settings.debug {
+        return;
+    }
+    for rc in rendered.0.values() {
+        commands.entity(rc.mesh).remove::<Gizmo>();
+    }
+}
+
+/// Spawns solid block entities that were added and despawns ones that were removed.
 fn update_solid(
-    mut reader: MessageReader<RenderChunkSpawned>,
+    mut reader: MessageReader<RenderChunkUpdated>,
     chunks: Query<&Chunk>,
+    mut rendered: ResMut<RenderChunkMap>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
@@ -568,37 +676,56 @@ fn update_solid(
         *cube_material = materials.add(material);
     }
 
-    for &RenderChunkSpawned {
+    for &RenderChunkUpdated {
         render_chunk,
-        chunk,
+        chunk: chunk_id,
     } in reader.read()
     {
-        let chunk = chunks.get(chunk).unwrap();
+        let chunk = chunks.get(chunk_id).unwrap();
+        let Some(rc) = rendered.0.get_mut(&chunk_id) else {
+            continue;
+        };
+
+        rc.solids.retain(|&pos, &mut solid| {
+            if chunk.get_block(pos).is_solid() {
+                true
+            } else {
+                // `WakeCollidingEntitiesOnDespawn` wakes bodies resting on the block
+                commands.entity(solid).despawn();
+                false
+            }
+        });
 
         for z in 0..CHUNK_SIZE as i32 {
             for y in 0..CHUNK_HEIGHT as i32 {
                 for x in 0..CHUNK_SIZE as i32 {
-                    let block_id = chunk.get_block(IVec3::new(x, y, z));
-                    if !block_id.is_solid() {
+                    let pos = IVec3::new(x, y, z);
+                    let block_id = chunk.get_block(pos);
+                    if !block_id.is_solid() || rc.solids.contains_key(&pos) {
                         continue;
                     }
 
-                    commands.entity(render_chunk).with_child((
-                        Mesh3d(cube_mesh.clone()),
-                        MeshMaterial3d(cube_material.clone()),
-                        RigidBody::Static,
-                        ColliderConstructor::ConvexHullFromMesh,
-                        CollisionLayers::new(
-                            [GameLayer::Terrain],
-                            [GameLayer::Default, GameLayer::Character, GameLayer::Object],
-                        ),
-                        Transform::from_translation(Vec3::new(
-                            x as f32 + 0.5,
-                            y as f32 + 0.5,
-                            z as f32 + 0.5,
-                        )),
-                        Name::new(format!("Solid Block ({}, {}, {})", x, y, z)),
-                    ));
+                    let solid = commands
+                        .spawn((
+                            Mesh3d(cube_mesh.clone()),
+                            MeshMaterial3d(cube_material.clone()),
+                            RigidBody::Static,
+                            ColliderConstructor::ConvexHullFromMesh,
+                            CollisionLayers::new(
+                                [GameLayer::Terrain],
+                                [GameLayer::Default, GameLayer::Character, GameLayer::Object],
+                            ),
+                            Transform::from_translation(Vec3::new(
+                                x as f32 + 0.5,
+                                y as f32 + 0.5,
+                                z as f32 + 0.5,
+                            )),
+                            Name::new(format!("Solid Block ({}, {}, {})", x, y, z)),
+                            WakeCollidingEntitiesOnDespawn,
+                            ChildOf(render_chunk),
+                        ))
+                        .id();
+                    rc.solids.insert(pos, solid);
                 }
             }
         }
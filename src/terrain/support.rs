@@ -0,0 +1,398 @@
+//! Structural support for terrain. Blocks disconnected from the ground after an edit
+//! fall as rigid bodies and re-solidify into the grid when they come to rest.
+
+use avian3d::prelude::*;
+use bevy::{
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+
+use crate::{
+    item::ItemStack,
+    object::dropped_item::DropPlacer,
+    pause::PausableSystems,
+    physics::{GameLayer, WakeCollidingEntitiesOnDespawn},
+};
+
+use super::chunk::{BlockId, BlockRemoved, WriteBlocks};
+
+pub struct SupportPlugin;
+
+impl Plugin for SupportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FallingBlockAssets>().add_systems(
+            Update,
+            (settle_falling_blocks, check_support)
+                .chain()
+                .in_set(PausableSystems),
+        );
+    }
+}
+
+/// Islands larger than this are considered supported.
+const MAX_ISLAND_SIZE: usize = 1024;
+/// Linear speed under which a falling body is considered at rest.
+const REST_SPEED: f32 = 0.1;
+/// How long a falling body has to stay at rest before it is placed back into the grid.
+const REST_DURATION: f32 = 0.5;
+/// Falling bodies below this height are despawned.
+const MIN_FALL_HEIGHT: f32 = -64.0;
+/// How far up to search for free space when re-solidifying a block.
+const MAX_PLACE_OFFSET: i32 = 4;
+
+/// A rigid body made of blocks that lost their support.
+#[derive(Component)]
+pub struct FallingBlocks {
+    /// Blocks and their offset from the body origin to the block center.
+    blocks: Vec<(Vec3, BlockId)>,
+    rest_timer: Timer,
+}
+
+#[derive(Resource)]
+struct FallingBlockAssets {
+    cube_mesh: Handle<Mesh>,
+    materials: HashMap<BlockId, Handle<StandardMaterial>>,
+    fallback_material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for FallingBlockAssets {
+    fn from_world(world: &mut World) -> Self {
+        let cube_mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::from(Cuboid::from_length(1.0)));
+
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let mut material_map = HashMap::new();
+        material_map.insert(
+            BlockId(1),
+            materials.add(StandardMaterial::from(Color::srgb(0.0, 1.0, 0.0))),
+        );
+        material_map.insert(
+            BlockId(2),
+            materials.add(StandardMaterial::from(Color::srgb(0.5, 0.5, 0.5))),
+        );
+        let fallback_material = materials.add(StandardMaterial::from(Color::srgb(1.0, 0.0, 1.0)));
+
+        FallingBlockAssets {
+            cube_mesh,
+            materials: material_map,
+            fallback_material,
+        }
+    }
+}
+
+/// Finds blocks that lost their connection to the ground and turns them into falling bodies.
+fn check_support(
+    mut removed: MessageReader<BlockRemoved>,
+    mut blocks: WriteBlocks,
+    mut commands: Commands,
+    assets: Res<FallingBlockAssets>,
+) -> Result<()> {
+    let removed = removed.read().map(|r| r.0).collect::<Vec<_>>();
+    if removed.is_empty() {
+        return Ok(());
+    }
+
+    // Shared between searches so that the ground is only walked once per pass
+    let mut supported = HashSet::new();
+    let mut islands = vec![];
+
+    for position in removed {
+        for dir in NEIGHBORS {
+            let start = position + dir;
+            if islands
+                .iter()
+                .any(|island: &Vec<IVec3>| island.contains(&start))
+            {
+                continue;
+            }
+            if let Some(island) = find_unsupported_island(start, &mut supported, |pos| {
+                blocks.get_block(pos).ok().map(|(block, _)| block)
+            }) {
+                islands.push(island);
+            }
+        }
+    }
+
+    for island in islands {
+        let mut island_blocks = vec![];
+        for &pos in &island {
+            island_blocks.push((pos, blocks.get_block(pos)?.0));
+        }
+        for &pos in &island {
+            blocks.set_block(pos, BlockId::AIR)?;
+        }
+        spawn_falling_blocks(&mut commands, &assets, &island_blocks);
+    }
+
+    Ok(())
+}
+
+const NEIGHBORS: [IVec3; 6] = [
+    IVec3::Y,
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Z,
+    IVec3::NEG_Z,
+    IVec3::NEG_Y,
+];
+
+/// Walks the blocks connected to `start` and returns them if none of them touches the ground.
+///
+/// `get_block` returns `None` for unloaded positions, which count as supported.
+/// Positions found to be supported are added to `supported`.
+fn find_unsupported_island(
+    start: IVec3,
+    supported: &mut HashSet<IVec3>,
+    get_block: impl Fn(IVec3) -> Option<BlockId>,
+) -> Option<Vec<IVec3>> {
+    if supported.contains(&start) || get_block(start).is_none_or(|b| b == BlockId::AIR) {
+        return None;
+    }
+
+    let mut visited = HashSet::new();
+    visited.insert(start);
+    let mut stack = vec![start];
+    let mut is_supported = false;
+
+    // Depth-first, going down first, to reach the ground quickly
+    while let Some(pos) = stack.pop() {
+        if pos.y == 0 || supported.contains(&pos) || visited.len() > MAX_ISLAND_SIZE {
+            is_supported = true;
+            break;
+        }
+        for dir in NEIGHBORS {
+            let next = pos + dir;
+            if visited.contains(&next) {
+                continue;
+            }
+            match get_block(next) {
+                None => {
+                    is_supported = true;
+                    break;
+                }
+                Some(BlockId::AIR) => {}
+                Some(_) => {
+                    visited.insert(next);
+                    stack.push(next);
+                }
+            }
+        }
+        if is_supported {
+            break;
+        }
+    }
+
+    if is_supported {
+        supported.extend(visited);
+        None
+    } else {
+        Some(visited.into_iter().collect())
+    }
+}
+
+fn spawn_falling_blocks(
+    commands: &mut Commands,
+    assets: &FallingBlockAssets,
+    blocks: &[(IVec3, BlockId)],
+) {
+    let center = blocks
+        .iter()
+        .map(|(pos, _)| pos.as_vec3() + Vec3::splat(0.5))
+        .sum::<Vec3>()
+        / blocks.len() as f32;
+
+    let blocks = blocks
+        .iter()
+        .map(|&(pos, block)| (pos.as_vec3() + Vec3::splat(0.5) - center, block))
+        .collect::<Vec<_>>();
+
+    debug!("Spawning {} falling blocks at {}", blocks.len(), center);
+
+    let children = blocks
+        .iter()
+        .map(|&(offset, block)| {
+            (
+                Mesh3d(assets.cube_mesh.clone()),
+                MeshMaterial3d(
+                    assets
+                        .materials
+                        .get(&block)
+                        .cloned()
+                        .unwrap_or_else(|| assets.fallback_material.clone()),
+                ),
+                // Slightly smaller than a block so it does not get stuck in neighbors
+                Collider::cuboid(0.95, 0.95, 0.95),
+                CollisionLayers::new(
+                    [GameLayer::Object],
+                    [GameLayer::Terrain, GameLayer::Character, GameLayer::Object],
+                ),
+                Transform::from_translation(offset),
+                // Wake bodies resting on this one when it settles
+                WakeCollidingEntitiesOnDespawn,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    commands.spawn((
+        Name::new("Falling Blocks"),
+        FallingBlocks {
+            blocks,
+            rest_timer: Timer::from_seconds(REST_DURATION, TimerMode::Once),
+        },
+        RigidBody::Dynamic,
+        // Keep blocks aligned to the grid
+        LockedAxes::ROTATION_LOCKED,
+        Transform::from_translation(center),
+        Visibility::default(),
+        Children::spawn(SpawnIter(children.into_iter())),
+    ));
+}
+
+/// Places falling blocks back into the grid once they come to rest.
+fn settle_falling_blocks(
+    mut query: Query<(Entity, &mut FallingBlocks, &LinearVelocity, &Transform)>,
+    mut blocks: WriteBlocks,
+    mut commands: Commands,
+    mut placer: DropPlacer,
+    time: Res<Time>,
+) -> Result<()> {
+    for (entity, mut falling, velocity, transform) in &mut query {
+        if transform.translation.y < MIN_FALL_HEIGHT {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        if velocity.length() > REST_SPEED {
+            falling.rest_timer.reset();
+            continue;
+        }
+        if !falling.rest_timer.tick(time.delta()).is_finished() {
+            continue;
+        }
+
+        let mut placed: Vec<(IVec3, BlockId)> = vec![];
+        let mut dropped = vec![];
+        for &(offset, block) in &falling.blocks {
+            let world_pos = (transform.translation + offset).floor().as_ivec3();
+
+            let free_pos = (0..=MAX_PLACE_OFFSET)
+                .map(|dy| world_pos + IVec3::Y * dy)
+                .find(|&pos| {
+                    !placed.iter().any(|&(p, _)| p == pos)
+                        && blocks.get_block(pos).is_ok_and(|(b, _)| b == BlockId::AIR)
+                });
+
+            if let Some(pos) = free_pos {
+                placed.push((pos, block));
+            } else {
+                dropped.push((world_pos, block));
+            }
+        }
+
+        // A body resting on something other than terrain, like another falling body, would
+        // float once that moves away. Keep it falling until it rests on supported blocks.
+        let get_block = |pos: IVec3| {
+            placed
+                .iter()
+                .find(|&&(p, _)| p == pos)
+                .map(|&(_, block)| block)
+                .or_else(|| blocks.get_block(pos).ok().map(|(block, _)| block))
+        };
+        let mut supported = HashSet::new();
+        if placed
+            .iter()
+            .any(|&(pos, _)| find_unsupported_island(pos, &mut supported, get_block).is_some())
+        {
+            falling.rest_timer.reset();
+            continue;
+        }
+
+        commands.entity(entity).despawn();
+
+        for (pos, block) in placed {
+            blocks.set_block(pos, block)?;
+        }
+        for (pos, block) in dropped {
+            // No space left, drop as an item instead
+            placer.drop_items(
+                pos.as_vec3() + Vec3::splat(0.5),
+                [ItemStack::new(block.as_item_id(), 1)?],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: BlockId = BlockId(2);
+
+    /// Looks up blocks in `grid`. Positions not in it are air.
+    fn lookup(grid: &HashSet<IVec3>) -> impl Fn(IVec3) -> Option<BlockId> {
+        move |pos| {
+            Some(if grid.contains(&pos) {
+                STONE
+            } else {
+                BlockId::AIR
+            })
+        }
+    }
+
+    #[test]
+    fn column_on_the_ground_is_supported() {
+        let grid = (0..4).map(|y| IVec3::new(0, y, 0)).collect::<HashSet<_>>();
+        let mut supported = HashSet::new();
+
+        assert_eq!(
+            find_unsupported_island(IVec3::new(0, 3, 0), &mut supported, lookup(&grid)),
+            None
+        );
+        assert!(supported.contains(&IVec3::new(0, 3, 0)));
+        assert!(supported.contains(&IVec3::new(0, 0, 0)));
+    }
+
+    #[test]
+    fn overhang_falls_when_its_column_is_cut() {
+        let mut grid = (0..4).map(|y| IVec3::new(0, y, 0)).collect::<HashSet<_>>();
+        grid.extend([IVec3::new(1, 3, 0), IVec3::new(2, 3, 0)]);
+
+        let overhang = IVec3::new(2, 3, 0);
+        assert_eq!(
+            find_unsupported_island(overhang, &mut HashSet::new(), lookup(&grid)),
+            None
+        );
+
+        grid.remove(&IVec3::new(0, 1, 0));
+        let mut island =
+            find_unsupported_island(overhang, &mut HashSet::new(), lookup(&grid)).unwrap();
+        island.sort_by_key(|pos| (pos.x, pos.y));
+        assert_eq!(
+            island,
+            [
+                IVec3::new(0, 2, 0),
+                IVec3::new(0, 3, 0),
+                IVec3::new(1, 3, 0),
+                IVec3::new(2, 3, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn islands_over_the_size_limit_are_supported() {
+        let side = 11;
+        assert!((side * side * side) as usize > MAX_ISLAND_SIZE);
+        let grid = (0..side)
+            .flat_map(|x| (0..side).flat_map(move |y| (0..side).map(move |z| (x, y, z))))
+            .map(|(x, y, z)| IVec3::new(x, y + 10, z))
+            .collect::<HashSet<_>>();
+
+        assert_eq!(
+            find_unsupported_island(IVec3::new(0, 10, 0), &mut HashSet::new(), lookup(&grid)),
+            None
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Time of day, cycling over [`TimeOfDay::day_length`], and the light level it gives.
+
+use std::{f32::consts::TAU, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::{
+    pause::PausableSystems,
+    terrain::chunk::{CHUNK_HEIGHT, ReadBlocks},
+};
+
+pub struct TimeOfDayPlugin;
+
+impl Plugin for TimeOfDayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>()
+            .add_systems(Update, advance_time_of_day.in_set(PausableSystems));
+    }
+}
+
+/// Light level of blocks open to the sky at noon.
+pub const MAX_LIGHT: u8 = 15;
+/// Light level of blocks open to the sky at midnight.
+const MOON_LIGHT: u8 = 4;
+
+#[derive(Resource, Clone, Debug)]
+pub struct TimeOfDay {
+    /// Fraction of the day since midnight, in `0.0..1.0`.
+    fraction: f32,
+    pub day_length: Duration,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        TimeOfDay {
+            // Morning
+            fraction: 0.3,
+            day_length: Duration::from_secs(20 * 60),
+        }
+    }
+}
+
+impl TimeOfDay {
+    /// Fraction of the day since midnight, in `0.0..1.0`. Noon is `0.5`.
+    pub fn fraction(&self) -> f32 {
+        self.fraction
+    }
+
+    pub fn set_fraction(&mut self, fraction: f32) {
+        self.fraction = fraction.rem_euclid(1.0);
+    }
+
+    /// Light level of blocks open to the sky.
+    pub fn sky_light(&self) -> u8 {
+        // 1 at noon, -1 at midnight
+        let sun = -(self.fraction * TAU).cos();
+        // Full daylight for most of the day, quickly falling at dusk
+        let daylight = (sun * 2.0 + 0.5).clamp(0.0, 1.0);
+        MOON_LIGHT + ((MAX_LIGHT - MOON_LIGHT) as f32 * daylight).round() as u8
+    }
+
+    /// Light level at `cell`. Only sky light is simulated, so cells below any solid block
+    /// are dark.
+    pub fn light_level(&self, blocks: &ReadBlocks, cell: IVec3) -> u8 {
+        let covered = (cell.y + 1..CHUNK_HEIGHT as i32).any(|y| {
+            blocks
+                .get_block(cell.with_y(y))
+                .is_ok_and(|(block, _)| block.is_terrain() || block.is_solid())
+        });
+        if covered { 0 } else { self.sky_light() }
+    }
+}
+
+fn advance_time_of_day(mut time_of_day: ResMut<TimeOfDay>, time: Res<Time>) {
+    let day = time_of_day.day_length.as_secs_f32();
+    if day > 0.0 {
+        let fraction = time_of_day.fraction + time.delta_secs() / day;
+        time_of_day.set_fraction(fraction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sky_light_peaks_at_noon() {
+        let mut time_of_day = TimeOfDay::default();
+
+        time_of_day.set_fraction(0.5);
+        assert_eq!(time_of_day.sky_light(), MAX_LIGHT);
+
+        time_of_day.set_fraction(1.0);
+        assert_eq!(time_of_day.fraction(), 0.0);
+        assert_eq!(time_of_day.sky_light(), MOON_LIGHT);
+
+        time_of_day.set_fraction(0.75);
+        let dusk = time_of_day.sky_light();
+        assert!(MOON_LIGHT < dusk && dusk < MAX_LIGHT);
+    }
+}
@@ -1,6 +1,6 @@
 use bevy::{ecs::relationship::RelatedSpawner, input::mouse::AccumulatedMouseScroll, prelude::*};
 
-use crate::{inventory::Inventory, ui::item_icon::ItemIconNode};
+use crate::{inventory::Inventory, item::ItemRegistry, ui::item_icon::ItemIconNode};
 
 pub struct HotbarPlugin;
 
@@ -47,7 +47,7 @@ pub fn build_hotbar(
             for i in 0..hotbar_num {
                 parent
                     .spawn((
-                        ItemIconNode(None),
+                        ItemIconNode::default(),
                         Name::new(format!("Hotbar Slot {}", i + 1)),
                         Node {
                             width: px(48.0),
@@ -86,6 +86,7 @@ fn update_hotbar(
     mut item_icons: Query<(Entity, &ItemIconNode, &mut BorderColor)>,
     children: Query<&Children>,
     mut texts: Query<&mut Text>,
+    registry: Res<ItemRegistry>,
     mut commands: Commands,
 ) -> Result<()> {
     for (hotbar, hotbar_children) in hotbars.iter() {
@@ -111,11 +112,11 @@ fn update_hotbar(
                 continue;
             };
 
-            let item_id = slot.as_ref().map(|is| is.item_id);
+            let icon = ItemIconNode::new(slot.as_ref(), &registry);
             let item_num = slot.as_ref().map(|is| is.quantity()).unwrap_or(0);
 
-            if item_icon.0 != item_id {
-                commands.entity(child).insert(ItemIconNode(item_id));
+            if *item_icon != icon {
+                commands.entity(child).insert(icon);
             }
 
             for id in children.iter_leaves(item_icon_id) {
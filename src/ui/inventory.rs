@@ -1,6 +1,15 @@
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, picking::hover::HoverMap, prelude::*};
 
-use crate::{inventory::Inventory, ui::item_icon::ItemIconNode};
+use crate::{
+    character::player::PlayerCamera,
+    inventory::Inventory,
+    item::{
+        ItemRegistry, ItemStack,
+        recipe::{RecipeBook, consume_ingredients},
+    },
+    object::dropped_item::DropPlacer,
+    ui::item_icon::ItemIconNode,
+};
 
 pub struct InventoryUiPlugin;
 
@@ -11,7 +20,12 @@ impl Plugin for InventoryUiPlugin {
                 Update,
                 (inventory_toggle, update_inventory_visibility).chain(),
             )
-            .add_systems(Update, update_inventory_slots);
+            .add_systems(Update, update_inventory_slots)
+            .add_systems(
+                Update,
+                update_crafting_output.run_if(in_state(InventoryState::Open)),
+            )
+            .add_systems(OnExit(InventoryState::Open), return_crafting_grid_items);
     }
 }
 
@@ -29,10 +43,20 @@ pub struct InventoryUiRoot {
     #[expect(dead_code)]
     pub chest_inventory: Option<Entity>,
     pub inventory: Entity,
+    /// [`Inventory`] holding the items in the crafting grid.
+    pub crafting_grid: Entity,
+}
+
+/// Slot showing the item at `index` of `inventory`.
+#[derive(Component)]
+struct InventoryUiSlot {
+    inventory: Entity,
+    index: usize,
 }
 
+/// Slot previewing the output of the recipe matching the crafting grid. Clicking it crafts.
 #[derive(Component)]
-struct InventoryUiSlot(usize);
+struct CraftingOutputSlot;
 
 #[derive(Component)]
 struct SlotBlockIcon;
@@ -46,26 +70,41 @@ const INVENTORY_BORDER_TOP: Color = Color::srgba_u8(0xD1, 0xD3, 0xD4, 0xFF);
 // #86B0BD
 const INVENTORY_BORDER_BOTTOM: Color = Color::srgba_u8(0x86, 0xB0, 0xBD, 0xFF);
 
+const SLOT_SIZE: f32 = 60.0;
+const SLOT_GAP: f32 = 8.0;
+/// Number of columns and rows of the crafting grid.
+const CRAFTING_GRID_WIDTH: usize = 3;
+
 pub fn build_inventory_root(
     In(inventory): In<Entity>,
     mut commands: Commands,
     inventories: Query<(NameOrEntity, &Inventory)>,
+    registry: Res<ItemRegistry>,
 ) {
     // let uv_debug_image = images.add(uv_debug_texture());
 
-    let slot_size = 60.0;
-    let slot_gap = 8.0;
-
     let (name, data) = inventories
         .get(inventory)
         .expect("Inventory entity does not exist");
 
+    let crafting_grid = commands
+        .spawn((
+            Name::new(format!("Crafting Grid of {}", name)),
+            Inventory {
+                slots: vec![None; CRAFTING_GRID_WIDTH * CRAFTING_GRID_WIDTH],
+                hotbar: None,
+            },
+            ChildOf(inventory),
+        ))
+        .id();
+
     commands
         .spawn((
             Name::new(format!("Inventory UI Root for {}", name)),
             InventoryUiRoot {
                 chest_inventory: None,
                 inventory,
+                crafting_grid,
             },
             Node {
                 display: Display::None,
@@ -89,68 +128,266 @@ pub fn build_inventory_root(
             BackgroundColor(INVENTORY_BACKGROUND),
         ))
         .with_children(|parent| {
+            parent
+                .spawn((
+                    Name::new("Crafting"),
+                    Node {
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(16.0),
+                        margin: UiRect::bottom(Val::Px(16.0)),
+                        ..default()
+                    },
+                ))
+                .with_children(|crafting| {
+                    crafting
+                        .spawn((
+                            Name::new("Crafting Grid"),
+                            Node {
+                                display: Display::Flex,
+                                flex_wrap: FlexWrap::Wrap,
+                                width: Val::Px(CRAFTING_GRID_WIDTH as f32 * (SLOT_SIZE + SLOT_GAP)),
+                                ..default()
+                            },
+                        ))
+                        .with_children(|grid| {
+                            for index in 0..CRAFTING_GRID_WIDTH * CRAFTING_GRID_WIDTH {
+                                spawn_slot(
+                                    grid,
+                                    format!("Crafting Slot {}", index),
+                                    None,
+                                    &registry,
+                                )
+                                .insert(InventoryUiSlot {
+                                    inventory: crafting_grid,
+                                    index,
+                                })
+                                .observe(move_dropped_stack)
+                                .observe(throw_dragged_stack);
+                            }
+                        });
+                    crafting.spawn((
+                        Text::new("->"),
+                        TextColor(Color::BLACK),
+                        TextFont {
+                            font_size: 24.0,
+                            ..default()
+                        },
+                    ));
+                    spawn_slot(crafting, "Crafting Output".into(), None, &registry)
+                        .insert(CraftingOutputSlot)
+                        .observe(craft_recipe);
+                });
+
             parent
                 .spawn((
                     Name::new("Grid"),
                     Node {
                         display: Display::Flex,
                         flex_wrap: FlexWrap::Wrap,
-                        // width: Val::Px(columns as f32 * (slot_size + slot_gap)),
+                        // width: Val::Px(columns as f32 * (SLOT_SIZE + SLOT_GAP)),
                         ..default()
                     },
                 ))
                 .with_children(|grid| {
                     for i in 0..data.slots.len() {
-                        let mut slot = grid.spawn((
-                            Name::new(format!("Slot {}", i)),
-                            InventoryUiSlot(i),
-                            Node {
-                                width: Val::Px(slot_size),
-                                height: Val::Px(slot_size),
-                                margin: UiRect::all(Val::Px(slot_gap * 0.5)),
-                                border: UiRect::all(Val::Px(2.0)),
-                                ..default()
-                            },
-                            BackgroundColor(INVENTORY_SLOT_BACKGROUND),
-                            BorderColor::all(INVENTORY_SLOT_BACKGROUND.darker(0.2)),
-                            BorderRadius::all(px(2.0)),
-                        ));
-                        slot.with_children(|slot| {
-                            slot.spawn((
-                                ItemIconNode(data.slots[i].as_ref().map(|is| is.item_id)),
-                                Node {
-                                    position_type: PositionType::Absolute,
-                                    top: Val::Px(0.0),
-                                    left: Val::Px(0.0),
-                                    width: percent(100.0),
-                                    height: percent(100.0),
-                                    ..default()
-                                },
-                                Visibility::Hidden,
-                                SlotBlockIcon,
-                            ));
-                            slot.spawn((
-                                Name::new("Count"),
-                                Node {
-                                    position_type: PositionType::Absolute,
-                                    right: Val::Px(2.0),
-                                    bottom: Val::Px(2.0),
-                                    ..default()
-                                },
-                                Text::new(String::new()),
-                                TextColor(Color::BLACK),
-                                TextFont {
-                                    font_size: 12.0,
-                                    ..default()
-                                },
-                                TextBackgroundColor(Color::WHITE),
-                            ));
-                        });
+                        spawn_slot(
+                            grid,
+                            format!("Slot {}", i),
+                            data.slots[i].as_ref(),
+                            &registry,
+                        )
+                        .insert(InventoryUiSlot {
+                            inventory,
+                            index: i,
+                        })
+                        .observe(move_dropped_stack)
+                        .observe(throw_dragged_stack);
                     }
                 });
         });
 }
 
+/// Spawns a slot showing `item_stack`, with its icon and count.
+fn spawn_slot<'a>(
+    parent: &'a mut ChildSpawnerCommands,
+    name: String,
+    item_stack: Option<&ItemStack>,
+    registry: &ItemRegistry,
+) -> EntityCommands<'a> {
+    let mut slot = parent.spawn((
+        Name::new(name),
+        Node {
+            width: Val::Px(SLOT_SIZE),
+            height: Val::Px(SLOT_SIZE),
+            margin: UiRect::all(Val::Px(SLOT_GAP * 0.5)),
+            border: UiRect::all(Val::Px(2.0)),
+            ..default()
+        },
+        BackgroundColor(INVENTORY_SLOT_BACKGROUND),
+        BorderColor::all(INVENTORY_SLOT_BACKGROUND.darker(0.2)),
+        BorderRadius::all(px(2.0)),
+    ));
+    slot.with_children(|slot| {
+        slot.spawn((
+            ItemIconNode::new(item_stack, registry),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(0.0),
+                left: Val::Px(0.0),
+                width: percent(100.0),
+                height: percent(100.0),
+                ..default()
+            },
+            Visibility::Hidden,
+            SlotBlockIcon,
+        ));
+        slot.spawn((
+            Name::new("Count"),
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(2.0),
+                bottom: Val::Px(2.0),
+                ..default()
+            },
+            Text::new(String::new()),
+            TextColor(Color::BLACK),
+            TextFont {
+                font_size: 12.0,
+                ..default()
+            },
+            TextBackgroundColor(Color::WHITE),
+        ));
+    });
+    slot
+}
+
+/// Throws the whole stack in a slot dragged out of the inventory window.
+fn throw_dragged_stack(
+    drag_end: On<Pointer<DragEnd>>,
+    slots: Query<&InventoryUiSlot>,
+    roots: Query<&InventoryUiRoot>,
+    parents: Query<&ChildOf>,
+    hover_map: Res<HoverMap>,
+    mut inventories: Query<&mut Inventory>,
+    camera: Single<&GlobalTransform, With<PlayerCamera>>,
+    mut placer: DropPlacer,
+) -> Result<()> {
+    let slot_id = drag_end.event_target();
+    let slot = slots.get(slot_id)?;
+    let root_id = parents
+        .iter_ancestors(slot_id)
+        .find(|&e| roots.contains(e))
+        .ok_or("Inventory slot is not in an inventory window")?;
+
+    let over_window = hover_map.get(&drag_end.pointer_id).is_some_and(|hits| {
+        hits.keys()
+            .any(|&hit| hit == root_id || parents.iter_ancestors(hit).any(|e| e == root_id))
+    });
+    if over_window {
+        return Ok(());
+    }
+
+    let mut inventory = inventories.get_mut(slot.inventory)?;
+    if let Some(item_stack) = inventory.take(slot.index, ItemStack::MAX_QUANTITY) {
+        placer.throw_item(camera.translation(), camera.forward(), item_stack)?;
+    }
+
+    Ok(())
+}
+
+/// Moves the stack in a slot dropped onto another slot, adding it to the stack there or
+/// swapping the two.
+fn move_dropped_stack(
+    drag_drop: On<Pointer<DragDrop>>,
+    slots: Query<&InventoryUiSlot>,
+    parents: Query<&ChildOf>,
+    mut inventories: Query<&mut Inventory>,
+    registry: Res<ItemRegistry>,
+) -> Result<()> {
+    let target = slots.get(drag_drop.event_target())?;
+    // The drag may have started on the icon or count of the slot
+    let Some(source) = std::iter::once(drag_drop.dropped)
+        .chain(parents.iter_ancestors(drag_drop.dropped))
+        .find_map(|e| slots.get(e).ok())
+    else {
+        return Ok(());
+    };
+    if (source.inventory, source.index) == (target.inventory, target.index) {
+        return Ok(());
+    }
+
+    let Some(item_stack) = inventories.get_mut(source.inventory)?.slots[source.index].take() else {
+        return Ok(());
+    };
+    let remaining = {
+        let mut inventory = inventories.get_mut(target.inventory)?;
+        match &mut inventory.slots[target.index] {
+            Some(existing) if existing.stacks_with(&item_stack) => {
+                let room = registry
+                    .max_stack(existing.item_id)
+                    .saturating_sub(existing.quantity());
+                let moved = item_stack.quantity().min(room);
+                if moved > 0 {
+                    existing.set_quantity(existing.quantity() + moved)?;
+                }
+                if moved < item_stack.quantity() {
+                    Some(item_stack.with_quantity(item_stack.quantity() - moved)?)
+                } else {
+                    None
+                }
+            }
+            slot => slot.replace(item_stack),
+        }
+    };
+    inventories.get_mut(source.inventory)?.slots[source.index] = remaining;
+
+    Ok(())
+}
+
+/// Crafts the recipe matching the crafting grid into the inventory. Shift-click crafts as many
+/// times as the ingredients and the room in the inventory allow.
+fn craft_recipe(
+    click: On<Pointer<Click>>,
+    roots: Query<&InventoryUiRoot>,
+    parents: Query<&ChildOf>,
+    mut inventories: Query<&mut Inventory>,
+    recipes: RecipeBook,
+    registry: Res<ItemRegistry>,
+    key: Res<ButtonInput<KeyCode>>,
+) -> Result<()> {
+    if click.event().button != PointerButton::Primary {
+        return Ok(());
+    }
+    let root = parents
+        .iter_ancestors(click.event_target())
+        .find_map(|e| roots.get(e).ok())
+        .ok_or("Crafting output slot is not in an inventory window")?;
+    let craft_all = key.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    loop {
+        let grid = inventories.get(root.crafting_grid)?;
+        let Some(recipe) = recipes.find(&grid.slots, CRAFTING_GRID_WIDTH) else {
+            break;
+        };
+        let output = recipe.output_stack(&registry)?;
+
+        let mut inventory = inventories.get_mut(root.inventory)?;
+        if inventory.room_for(&output, &registry) < output.quantity() {
+            break;
+        }
+        inventory
+            .add_item_stack(output, &registry)
+            .map_err(|_| "No room for crafted items")?;
+        consume_ingredients(&mut inventories.get_mut(root.crafting_grid)?.slots)?;
+
+        if !craft_all {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
 fn update_inventory_visibility(
     state: Res<State<InventoryState>>,
     mut roots: Query<&mut Node, With<InventoryUiRoot>>,
@@ -178,53 +415,116 @@ fn inventory_toggle(
     }
 }
 
+/// Moves the items left in the crafting grid back into the inventory when the window closes,
+/// throwing whatever doesn't fit.
+fn return_crafting_grid_items(
+    roots: Query<&InventoryUiRoot>,
+    mut inventories: Query<&mut Inventory>,
+    registry: Res<ItemRegistry>,
+    camera: Single<&GlobalTransform, With<PlayerCamera>>,
+    mut placer: DropPlacer,
+) -> Result<()> {
+    for root in &roots {
+        let stacks: Vec<ItemStack> = inventories
+            .get_mut(root.crafting_grid)?
+            .slots
+            .iter_mut()
+            .filter_map(Option::take)
+            .collect();
+        let mut inventory = inventories.get_mut(root.inventory)?;
+        for item_stack in stacks {
+            if let Err(remaining) = inventory.add_item_stack(item_stack, &registry) {
+                placer.throw_item(camera.translation(), camera.forward(), remaining)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
 fn update_inventory_slots(
     roots: Query<(Entity, Ref<InventoryUiRoot>)>,
     slots: Query<&InventoryUiSlot>,
     inventories: Query<Ref<Inventory>>,
     children: Query<&Children>,
-    item_icon: Query<&ItemIconNode>,
-    mut texts: Query<&mut Text>,
-    mut block_icons: Query<&mut Visibility, With<SlotBlockIcon>>,
-    mut commands: Commands,
+    mut contents: SlotContents,
 ) -> Result<()> {
     for (root_id, root) in &roots {
-        let inventory = inventories.get(root.inventory)?;
-        if !root.is_added() && !inventory.is_changed() {
-            continue;
-        }
-
         for child in children.iter_descendants(root_id) {
             let Ok(slot) = slots.get(child) else {
                 continue;
             };
+            let inventory = inventories.get(slot.inventory)?;
+            if !root.is_added() && !inventory.is_changed() {
+                continue;
+            }
 
-            for schild in children.get(child)?.iter() {
-                if let Ok(item_icon) = item_icon.get(schild) {
-                    let maybe_item_id = inventory.slots[slot.0].as_ref().map(|is| is.item_id);
-                    if item_icon.0 != maybe_item_id {
-                        commands.entity(schild).insert(ItemIconNode(maybe_item_id));
-                    }
-                }
-                if let Ok(mut text) = texts.get_mut(schild) {
-                    text.0 = inventory.slots[slot.0]
-                        .as_ref()
-                        .map(|s| s.quantity().to_string())
-                        .unwrap_or_default();
+            contents.show(child, inventory.slots[slot.index].as_ref())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Previews the output of the recipe matching the crafting grid.
+fn update_crafting_output(
+    roots: Query<(Entity, &InventoryUiRoot)>,
+    outputs: Query<(), With<CraftingOutputSlot>>,
+    inventories: Query<&Inventory>,
+    children: Query<&Children>,
+    recipes: RecipeBook,
+    mut contents: SlotContents,
+) -> Result<()> {
+    for (root_id, root) in &roots {
+        let grid = inventories.get(root.crafting_grid)?;
+        let output = recipes
+            .find(&grid.slots, CRAFTING_GRID_WIDTH)
+            .map(|recipe| recipe.output_stack(&contents.registry))
+            .transpose()?;
+        for slot in children.iter_descendants(root_id) {
+            if outputs.contains(slot) {
+                contents.show(slot, output.as_ref())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Updates the icon and count of slots.
+#[derive(SystemParam)]
+struct SlotContents<'w, 's> {
+    children: Query<'w, 's, &'static Children>,
+    icons: Query<'w, 's, (&'static ItemIconNode, &'static mut Visibility), With<SlotBlockIcon>>,
+    texts: Query<'w, 's, &'static mut Text>,
+    registry: Res<'w, ItemRegistry>,
+    commands: Commands<'w, 's>,
+}
+
+impl SlotContents<'_, '_> {
+    /// Shows `item_stack` in `slot`, or nothing if `None`.
+    fn show(&mut self, slot: Entity, item_stack: Option<&ItemStack>) -> Result<()> {
+        for &child in self.children.get(slot)? {
+            if let Ok((item_icon, mut visibility)) = self.icons.get_mut(child) {
+                let icon = ItemIconNode::new(item_stack, &self.registry);
+                if *item_icon != icon {
+                    self.commands.entity(child).insert(icon);
                 }
+                visibility.set_if_neq(if item_stack.is_some() {
+                    Visibility::Visible
+                } else {
+                    Visibility::Hidden
+                });
             }
-            for schild in children.get(child)?.iter() {
-                if let Ok(mut visibility) = block_icons.get_mut(schild) {
-                    if inventory.slots[slot.0].is_some() {
-                        visibility.set_if_neq(Visibility::Visible);
-                    } else {
-                        visibility.set_if_neq(Visibility::Hidden);
-                    }
-                    break;
+            if let Ok(mut text) = self.texts.get_mut(child) {
+                let count = item_stack
+                    .map(|stack| stack.quantity().to_string())
+                    .unwrap_or_default();
+                if text.0 != count {
+                    text.0 = count;
                 }
             }
         }
+        Ok(())
     }
-
-    Ok(())
 }
@@ -6,22 +6,40 @@ use bevy::{
     shader::ShaderRef,
 };
 
-use crate::item::{ItemId, ItemImagesAdded, ItemRegistry};
+use crate::item::{ItemId, ItemImagesAdded, ItemRegistry, ItemStack};
 
 pub fn plugin(app: &mut App) {
     app.add_plugins(UiMaterialPlugin::<BlockIconMaterial>::default())
         .add_plugins(UiMaterialPlugin::<ItemIconMaterial>::default())
         .init_resource::<ItemIconRegistry>()
         .add_observer(add_item_icon)
+        .add_observer(update_durability_bar)
         .add_observer(item_image_updated)
         .add_systems(Startup, register_item_icon_materials);
 }
 
-/// UI node that displays an item icon.
-#[derive(Component)]
+/// UI node that displays an item icon, and a durability bar for worn tools.
+#[derive(Component, Clone, Debug, Default, PartialEq)]
 #[require(Node)]
 #[component(immutable)]
-pub struct ItemIconNode(pub Option<ItemId>);
+pub struct ItemIconNode {
+    pub item_id: Option<ItemId>,
+    /// Remaining durability as a fraction. The bar is hidden at `1.0`.
+    pub durability: Option<f32>,
+}
+
+impl ItemIconNode {
+    pub fn new(item_stack: Option<&ItemStack>, registry: &ItemRegistry) -> Self {
+        ItemIconNode {
+            item_id: item_stack.map(|stack| stack.item_id),
+            durability: item_stack.and_then(|stack| registry.durability_fraction(stack)),
+        }
+    }
+}
+
+/// Bar showing [`ItemIconNode::durability`].
+#[derive(Component)]
+struct DurabilityBar;
 
 fn add_item_icon(
     on: On<Insert, ItemIconNode>,
@@ -37,7 +55,7 @@ fn add_item_icon(
     let Ok((item_icon, block_material, item_material)) = query.get_mut(entity) else {
         return;
     };
-    let Some(item_id) = item_icon.0 else {
+    let Some(item_id) = item_icon.item_id else {
         commands.entity(entity).remove::<(
             MaterialNode<ItemIconMaterial>,
             MaterialNode<BlockIconMaterial>,
@@ -84,6 +102,41 @@ fn add_item_icon(
     }
 }
 
+fn update_durability_bar(
+    on: On<Insert, ItemIconNode>,
+    icons: Query<(&ItemIconNode, Option<&Children>)>,
+    bars: Query<(), With<DurabilityBar>>,
+    mut commands: Commands,
+) {
+    let Ok((item_icon, children)) = icons.get(on.entity) else {
+        return;
+    };
+    for &child in children.into_iter().flatten() {
+        if bars.contains(child) {
+            commands.entity(child).despawn();
+        }
+    }
+
+    let Some(fraction) = item_icon.durability.filter(|&fraction| fraction < 1.0) else {
+        return;
+    };
+    let fraction = fraction.clamp(0.0, 1.0);
+    commands.spawn((
+        DurabilityBar,
+        Node {
+            position_type: PositionType::Absolute,
+            left: percent(10.0),
+            bottom: px(3.0),
+            width: percent(80.0 * fraction),
+            height: px(3.0),
+            ..default()
+        },
+        // Green when new, red when about to break
+        BackgroundColor(Color::hsl(120.0 * fraction, 0.8, 0.5)),
+        ChildOf(on.entity),
+    ));
+}
+
 #[derive(AsBindGroup, Asset, TypePath, Debug, Clone)]
 pub struct ItemIconMaterial {
     #[texture(0)]